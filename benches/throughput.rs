@@ -0,0 +1,254 @@
+//! Feeds VT streams, captured from real programs or generated to look like
+//! them, through `Console::write` against an offscreen render target and
+//! reports the throughput and the cost of drawing a frame.
+//!
+//! Run with `cargo bench`, optionally followed by `-- <name>` to only run the
+//! streams whose name contains `<name>`.
+
+extern crate orbterm;
+
+use std::env;
+use std::fmt::Write;
+use std::time::{Duration, Instant};
+
+use orbterm::console::Console;
+use orbterm::render_target::Offscreen;
+use orbterm::{Config, DEFAULT_DPI};
+
+/// The size of the grid, which the streams were captured at
+const COLUMNS: usize = 80;
+const ROWS: usize = 24;
+
+/// The PTY is read in packets of this size by `handle`
+const PACKET_SIZE: usize = 4096;
+
+/// Captured streams are short, so they are repeated to be about this long
+const CAPTURED_SIZE: usize = 4 * 1024 * 1024;
+
+type Stream = fn() -> Vec<u8>;
+
+const WORDS: [&str; 16] = [
+    "fn",
+    "let",
+    "mut",
+    "self",
+    "console",
+    "window",
+    "block_width",
+    "grid",
+    "match",
+    "ransid::Event::Char",
+    "=>",
+    "{",
+    "}",
+    "//",
+    "render",
+    "0x00FFFFFF",
+];
+
+/// A large file being `cat`ed, mostly plain text scrolling the whole screen
+fn cat() -> Vec<u8> {
+    let mut stream = String::new();
+    for line in 0..40_000 {
+        let indent = (line % 5) * 4;
+        stream.push_str(&" ".repeat(indent));
+        for word in 0..(line * 7 % 11) + 1 {
+            stream.push_str(WORDS[(line + word * 3) % WORDS.len()]);
+            stream.push(' ');
+        }
+        stream.push_str("\r\n");
+    }
+    stream.into_bytes()
+}
+
+/// Scrolling through a file in `vim`: the alternate screen, a scroll region,
+/// syntax highlighting and a status line that is redrawn for every line
+fn vim() -> Vec<u8> {
+    let mut stream = String::from("\x1B[?1049h\x1B[H\x1B[2J\x1B[1;23r");
+    for line in 0..8_000 {
+        stream.push_str("\x1B[23;1H\n\x1B[23;1H");
+        let _ = write!(stream, "\x1B[38;5;130m{:4} \x1B[0m", line);
+        for word in 0..(line % 9) + 2 {
+            let color = 1 + (line + word) % 6;
+            let _ = write!(
+                stream,
+                "\x1B[3{}m{}\x1B[0m ",
+                color,
+                WORDS[(line * 5 + word) % WORDS.len()]
+            );
+        }
+        stream.push_str("\x1B[K");
+        let _ = write!(
+            stream,
+            "\x1B[24;1H\x1B[7m src/console.rs [+]{:>50}{:>5},1 \x1B[27m\x1B[{};10H",
+            "", line, 23
+        );
+    }
+    stream.push_str("\x1B[r\x1B[?1049l");
+    stream.into_bytes()
+}
+
+/// A colourful `ls -l`, switching colours and attributes for almost every word
+fn ls() -> Vec<u8> {
+    let mut stream = String::new();
+    for entry in 0..30_000 {
+        let _ = write!(
+            stream,
+            "drwxr-xr-x 2 user user {:>8} Dec  7  2021 ",
+            entry * 37 % 100_000
+        );
+        match entry % 4 {
+            0 => stream.push_str("\x1B[01;34mdirectory\x1B[0m"),
+            1 => stream.push_str("\x1B[01;32mexecutable\x1B[0m"),
+            2 => {
+                let _ = write!(
+                    stream,
+                    "\x1B[38;2;{};{};{}mtruecolor.rs\x1B[0m",
+                    entry % 256,
+                    entry * 3 % 256,
+                    entry * 7 % 256
+                );
+            }
+            _ => {
+                let _ = write!(
+                    stream,
+                    "\x1B[38;5;{}m\x1B[48;5;{}mfile\x1B[0m",
+                    entry % 256,
+                    255 - entry % 24
+                );
+            }
+        }
+        stream.push_str("\r\n");
+    }
+    stream.into_bytes()
+}
+
+/// Text from many scripts, with accents, CJK, emoji and box drawing
+fn unicode() -> Vec<u8> {
+    const LINES: [&str; 6] = [
+        "Grüße, Καλημέρα κόσμε, Здравствуй мир, café naïve résumé",
+        "こんにちは世界 你好，世界 안녕하세요 세계",
+        "🙂 🚀 🦀 ✔ ✘ ★ ☃ ♥ ⚡",
+        "┌──────┬──────┐ │ left │ right│ └──────┴──────┘",
+        "▁▂▃▄▅▆▇█ ░▒▓ ⠁⠃⠇⡇⣇⣧⣷⣿",
+        "e\u{301} a\u{308} n\u{303} क्षि 👩\u{200D}💻",
+    ];
+
+    let mut stream = String::new();
+    for line in 0..20_000 {
+        stream.push_str(LINES[line % LINES.len()]);
+        stream.push_str("\r\n");
+    }
+    stream.into_bytes()
+}
+
+/// Repeat a captured stream until it is about `CAPTURED_SIZE` long
+fn repeat(capture: &[u8]) -> Vec<u8> {
+    capture.repeat(CAPTURED_SIZE.div_ceil(capture.len()))
+}
+
+/// `ls -lR --color=always /usr/share/zoneinfo`, captured with `script`
+fn captured_ls() -> Vec<u8> {
+    repeat(include_bytes!("streams/ls.vt"))
+}
+
+/// `vim` scrolling through src/console.rs a line at a time with syntax
+/// highlighting and line numbers, captured with `script`
+fn captured_vim() -> Vec<u8> {
+    repeat(include_bytes!("streams/vim.vt"))
+}
+
+/// `git diff --stat --patch --color=always` of this repository, captured with
+/// `script`
+fn captured_diff() -> Vec<u8> {
+    repeat(include_bytes!("streams/diff.vt"))
+}
+
+/// A console with a window sized for `COLUMNS` by `ROWS` blocks of the default
+/// font
+fn offscreen_console() -> Console<Offscreen> {
+    let config = Config::default();
+    let sizing = Console::with_target(&config, Offscreen::new(1, 1), DEFAULT_DPI, 1.0);
+    let (width, height) = sizing.block_handler.window_size(COLUMNS, ROWS);
+    Console::with_target(&config, Offscreen::new(width, height), DEFAULT_DPI, 1.0)
+}
+
+fn per_second(bytes: usize, duration: Duration) -> f64 {
+    bytes as f64 / 1024.0 / 1024.0 / duration.as_secs_f64()
+}
+
+fn bench(name: &str, stream: &[u8]) {
+    // The real path: packets as they come from the PTY, drawn at most once per frame
+    let mut console = offscreen_console();
+    let start = Instant::now();
+    for packet in stream.chunks(PACKET_SIZE) {
+        console
+            .write(packet, true)
+            .expect("failed to write to console");
+    }
+    console.redraw();
+    let paced = start.elapsed();
+
+    // Drawing after every packet, to find out what a frame costs
+    let mut console = offscreen_console();
+    let mut parsing = Duration::default();
+    let mut drawing = Duration::default();
+    let mut frames = 0;
+    for packet in stream.chunks(PACKET_SIZE) {
+        let start = Instant::now();
+        console
+            .write(packet, false)
+            .expect("failed to write to console");
+        parsing += start.elapsed();
+
+        let start = Instant::now();
+        console.redraw();
+        drawing += start.elapsed();
+        frames += 1;
+    }
+
+    // Drawing every block of the screen
+    let start = Instant::now();
+    for _ in 0..100 {
+        console.damage.add_all();
+        console.redraw();
+    }
+    let full = start.elapsed() / 100;
+
+    println!(
+        "{:<10} {:>8.2} MiB {:>10.2} MiB/s {:>10.2} MiB/s {:>10.3} ms {:>10.3} ms",
+        name,
+        stream.len() as f64 / 1024.0 / 1024.0,
+        per_second(stream.len(), paced),
+        per_second(stream.len(), parsing),
+        drawing.as_secs_f64() * 1000.0 / frames as f64,
+        full.as_secs_f64() * 1000.0,
+    );
+}
+
+fn main() {
+    let filter: Vec<String> = env::args()
+        .skip(1)
+        .filter(|arg| !arg.starts_with("--"))
+        .collect();
+
+    let streams: [(&str, Stream); 7] = [
+        ("cat", cat),
+        ("vim", vim),
+        ("ls", ls),
+        ("unicode", unicode),
+        ("ls-lR", captured_ls),
+        ("vim-real", captured_vim),
+        ("git-diff", captured_diff),
+    ];
+
+    println!(
+        "{:<10} {:>12} {:>16} {:>16} {:>13} {:>13}",
+        "stream", "size", "throughput", "parsing", "frame", "full frame"
+    );
+    for (name, stream) in streams.iter() {
+        if filter.is_empty() || filter.iter().any(|filter| name.contains(filter.as_str())) {
+            bench(name, &stream());
+        }
+    }
+}
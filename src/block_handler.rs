@@ -1,22 +1,56 @@
 //! A selection of utility functions surrounding the scale of the cursor/block
 
+use fonts::{Fonts, Metrics};
 use Config;
-use BLOCK_WIDTH;
+use DEFAULT_FONT_SIZE;
+
+/// The smallest and largest the font can be zoomed to, in pixels
+const MIN_FONT_PIXELS: f32 = 6.0;
+const MAX_FONT_PIXELS: f32 = 200.0;
 
 pub struct BlockHandler {
     pub block_width: usize,
     pub block_height: usize,
-    pub default_block_width: usize,
-    pub default_block_height: usize,
+    pub metrics: Metrics,
+    /// The size of the font in pixels before zooming
+    pub font_pixels: f32,
+    pub zoom: f32,
+    pub zoom_step: f32,
+    line_height: f32,
+    letter_spacing: f32,
+    /// Space kept free around the grid, in pixels
+    pub padding_x: usize,
+    pub padding_y: usize,
+    /// Whether the pixels left over after fitting the grid into the window are
+    /// spread evenly around it, instead of being left on the right and bottom
+    pub center: bool,
+    /// Where the grid starts in the window
+    origin_x: usize,
+    origin_y: usize,
 }
 
 impl BlockHandler {
-    pub fn new(block_width: usize, block_height: usize) -> Self {
+    pub fn new(config: &Config, fonts: &Fonts, dpi: f32, zoom: f32) -> Self {
+        // There are 72 points to an inch
+        let font_pixels = config.font_size.unwrap_or(DEFAULT_FONT_SIZE) * dpi / 72.0;
+        let line_height = config.line_height.unwrap_or(1.0);
+        let letter_spacing = config.letter_spacing.unwrap_or(0.0);
+        let metrics = fonts.metrics(font_pixels * zoom, line_height, letter_spacing);
+
         BlockHandler {
-            block_width,
-            block_height,
-            default_block_width: block_width,
-            default_block_height: block_height,
+            block_width: metrics.block_width,
+            block_height: metrics.block_height,
+            metrics,
+            font_pixels,
+            zoom,
+            zoom_step: config.zoom_step.unwrap_or(0.1),
+            line_height,
+            letter_spacing,
+            padding_x: config.padding_x.unwrap_or(0) as usize,
+            padding_y: config.padding_y.unwrap_or(0) as usize,
+            center: config.center.unwrap_or(false),
+            origin_x: config.padding_x.unwrap_or(0) as usize,
+            origin_y: config.padding_y.unwrap_or(0) as usize,
         }
     }
 }
@@ -27,47 +61,73 @@ impl BlockHandler {
     }
 
     pub fn get_block_from_coordinate(&self, x: usize, y: usize) -> (u16, u16) {
-        let x = (x / self.block_width) + 1;
-        let y = (y / self.block_height) + 1;
+        let x = (x.saturating_sub(self.origin_x) / self.block_width) + 1;
+        let y = (y.saturating_sub(self.origin_y) / self.block_height) + 1;
 
         (x as u16, y as u16)
     }
 
     pub fn get_pixels_from_block(&self, x: usize, y: usize) -> (usize, usize) {
-        let x = x * self.block_width;
-        let y = y * self.block_height;
+        let x = self.origin_x + x * self.block_width;
+        let y = self.origin_y + y * self.block_height;
 
         (x, y)
     }
 
-    pub fn how_many_blocks_fit(&self, window_width: usize, window_height: usize) -> (usize, usize) {
-        let width = window_width / self.block_width;
-        let height = window_height / self.block_height;
+    /// Fit as many blocks as possible inside the padding of the window, and
+    /// place the grid in it
+    pub fn how_many_blocks_fit(
+        &mut self,
+        window_width: usize,
+        window_height: usize,
+    ) -> (usize, usize) {
+        let inner_width = window_width.saturating_sub(2 * self.padding_x);
+        let inner_height = window_height.saturating_sub(2 * self.padding_y);
+        let width = inner_width / self.block_width;
+        let height = inner_height / self.block_height;
+
+        self.origin_x = self.padding_x;
+        self.origin_y = self.padding_y;
+        if self.center {
+            self.origin_x += (inner_width - width * self.block_width) / 2;
+            self.origin_y += (inner_height - height * self.block_height) / 2;
+        }
 
         (width, height)
     }
 
-    pub fn increase_block_size(&mut self, size: isize) {
-        self.block_width = (self.block_width as isize + size) as usize;
-        self.set_block_size(self.block_width);
+    /// The size of a window that fits the given number of blocks exactly
+    pub fn window_size(&self, columns: usize, rows: usize) -> (u32, u32) {
+        (
+            (columns * self.block_width + 2 * self.padding_x) as u32,
+            (rows * self.block_height + 2 * self.padding_y) as u32,
+        )
     }
 
-    pub fn reset_to_default(&mut self) {
-        self.block_width = self.default_block_width;
-        self.block_height = self.default_block_height;
+    /// Zoom in or out by the given number of steps
+    pub fn zoom_by(&mut self, fonts: &Fonts, steps: f32) {
+        let zoom = self.zoom + steps * self.zoom_step;
+        self.set_zoom(fonts, zoom);
     }
 
-    pub fn set_block_size(&mut self, block_width: usize) {
-        self.block_width = if block_width < 4 {
-            4
-        } else if block_width > 48 {
-            48
-        } else {
-            block_width
-        };
-        self.block_height = self.block_width * 2;
-
-        let scale = self.block_width as f32 / BLOCK_WIDTH as f32;
-        Config::set_initial_scale(scale).unwrap();
+    pub fn reset_to_default(&mut self, fonts: &Fonts) {
+        self.set_zoom(fonts, 1.0);
+    }
+
+    pub fn set_zoom(&mut self, fonts: &Fonts, zoom: f32) {
+        let min = MIN_FONT_PIXELS / self.font_pixels;
+        let max = MAX_FONT_PIXELS / self.font_pixels;
+        // Rounded, so that stepping back and forth ends up at the same zoom
+        self.zoom = (zoom.max(min).min(max) * 1000.0).round() / 1000.0;
+
+        self.metrics = fonts.metrics(
+            self.font_pixels * self.zoom,
+            self.line_height,
+            self.letter_spacing,
+        );
+        self.block_width = self.metrics.block_width;
+        self.block_height = self.metrics.block_height;
+
+        Config::set_initial_zoom(self.zoom).unwrap();
     }
 }
@@ -0,0 +1,541 @@
+//! Characters that are drawn by orbterm itself rather than taken from a font:
+//! box drawing, block elements, braille patterns and the Powerline symbols.
+//! They fill the block exactly, so that the lines and shapes of neighbouring
+//! blocks join up without gaps at any block size
+
+use std::cmp;
+
+use glyph_cache::Glyph;
+
+const NONE: u8 = 0;
+const LIGHT: u8 = 1;
+const HEAVY: u8 = 2;
+const DOUBLE: u8 = 3;
+
+/// The weight of the line going up, right, down and left from the centre of the
+/// block, for every character from U+2500 to U+257F. The dashed lines, arcs and
+/// diagonals are drawn separately
+#[rustfmt::skip]
+const LINES: [[u8; 4]; 0x80] = [
+    // ─ ━ │ ┃ ┄ ┅ ┆ ┇
+    [NONE, LIGHT, NONE, LIGHT], [NONE, HEAVY, NONE, HEAVY], [LIGHT, NONE, LIGHT, NONE], [HEAVY, NONE, HEAVY, NONE],
+    [NONE, LIGHT, NONE, LIGHT], [NONE, HEAVY, NONE, HEAVY], [LIGHT, NONE, LIGHT, NONE], [HEAVY, NONE, HEAVY, NONE],
+    // ┈ ┉ ┊ ┋ ┌ ┍ ┎ ┏
+    [NONE, LIGHT, NONE, LIGHT], [NONE, HEAVY, NONE, HEAVY], [LIGHT, NONE, LIGHT, NONE], [HEAVY, NONE, HEAVY, NONE],
+    [NONE, LIGHT, LIGHT, NONE], [NONE, HEAVY, LIGHT, NONE], [NONE, LIGHT, HEAVY, NONE], [NONE, HEAVY, HEAVY, NONE],
+    // ┐ ┑ ┒ ┓ └ ┕ ┖ ┗
+    [NONE, NONE, LIGHT, LIGHT], [NONE, NONE, LIGHT, HEAVY], [NONE, NONE, HEAVY, LIGHT], [NONE, NONE, HEAVY, HEAVY],
+    [LIGHT, LIGHT, NONE, NONE], [LIGHT, HEAVY, NONE, NONE], [HEAVY, LIGHT, NONE, NONE], [HEAVY, HEAVY, NONE, NONE],
+    // ┘ ┙ ┚ ┛ ├ ┝ ┞ ┟
+    [LIGHT, NONE, NONE, LIGHT], [LIGHT, NONE, NONE, HEAVY], [HEAVY, NONE, NONE, LIGHT], [HEAVY, NONE, NONE, HEAVY],
+    [LIGHT, LIGHT, LIGHT, NONE], [LIGHT, HEAVY, LIGHT, NONE], [HEAVY, LIGHT, LIGHT, NONE], [LIGHT, LIGHT, HEAVY, NONE],
+    // ┠ ┡ ┢ ┣ ┤ ┥ ┦ ┧
+    [HEAVY, LIGHT, HEAVY, NONE], [HEAVY, HEAVY, LIGHT, NONE], [LIGHT, HEAVY, HEAVY, NONE], [HEAVY, HEAVY, HEAVY, NONE],
+    [LIGHT, NONE, LIGHT, LIGHT], [LIGHT, NONE, LIGHT, HEAVY], [HEAVY, NONE, LIGHT, LIGHT], [LIGHT, NONE, HEAVY, LIGHT],
+    // ┨ ┩ ┪ ┫ ┬ ┭ ┮ ┯
+    [HEAVY, NONE, HEAVY, LIGHT], [HEAVY, NONE, LIGHT, HEAVY], [LIGHT, NONE, HEAVY, HEAVY], [HEAVY, NONE, HEAVY, HEAVY],
+    [NONE, LIGHT, LIGHT, LIGHT], [NONE, LIGHT, LIGHT, HEAVY], [NONE, HEAVY, LIGHT, LIGHT], [NONE, HEAVY, LIGHT, HEAVY],
+    // ┰ ┱ ┲ ┳ ┴ ┵ ┶ ┷
+    [NONE, LIGHT, HEAVY, LIGHT], [NONE, LIGHT, HEAVY, HEAVY], [NONE, HEAVY, HEAVY, LIGHT], [NONE, HEAVY, HEAVY, HEAVY],
+    [LIGHT, LIGHT, NONE, LIGHT], [LIGHT, LIGHT, NONE, HEAVY], [LIGHT, HEAVY, NONE, LIGHT], [LIGHT, HEAVY, NONE, HEAVY],
+    // ┸ ┹ ┺ ┻ ┼ ┽ ┾ ┿
+    [HEAVY, LIGHT, NONE, LIGHT], [HEAVY, LIGHT, NONE, HEAVY], [HEAVY, HEAVY, NONE, LIGHT], [HEAVY, HEAVY, NONE, HEAVY],
+    [LIGHT, LIGHT, LIGHT, LIGHT], [LIGHT, LIGHT, LIGHT, HEAVY], [LIGHT, HEAVY, LIGHT, LIGHT], [LIGHT, HEAVY, LIGHT, HEAVY],
+    // ╀ ╁ ╂ ╃ ╄ ╅ ╆ ╇
+    [HEAVY, LIGHT, LIGHT, LIGHT], [LIGHT, LIGHT, HEAVY, LIGHT], [HEAVY, LIGHT, HEAVY, LIGHT], [HEAVY, LIGHT, LIGHT, HEAVY],
+    [HEAVY, HEAVY, LIGHT, LIGHT], [LIGHT, LIGHT, HEAVY, HEAVY], [LIGHT, HEAVY, HEAVY, LIGHT], [HEAVY, HEAVY, LIGHT, HEAVY],
+    // ╈ ╉ ╊ ╋ ╌ ╍ ╎ ╏
+    [LIGHT, HEAVY, HEAVY, HEAVY], [HEAVY, LIGHT, HEAVY, HEAVY], [HEAVY, HEAVY, HEAVY, LIGHT], [HEAVY, HEAVY, HEAVY, HEAVY],
+    [NONE, LIGHT, NONE, LIGHT], [NONE, HEAVY, NONE, HEAVY], [LIGHT, NONE, LIGHT, NONE], [HEAVY, NONE, HEAVY, NONE],
+    // ═ ║ ╒ ╓ ╔ ╕ ╖ ╗
+    [NONE, DOUBLE, NONE, DOUBLE], [DOUBLE, NONE, DOUBLE, NONE], [NONE, DOUBLE, LIGHT, NONE], [NONE, LIGHT, DOUBLE, NONE],
+    [NONE, DOUBLE, DOUBLE, NONE], [NONE, NONE, LIGHT, DOUBLE], [NONE, NONE, DOUBLE, LIGHT], [NONE, NONE, DOUBLE, DOUBLE],
+    // ╘ ╙ ╚ ╛ ╜ ╝ ╞ ╟
+    [LIGHT, DOUBLE, NONE, NONE], [DOUBLE, LIGHT, NONE, NONE], [DOUBLE, DOUBLE, NONE, NONE], [LIGHT, NONE, NONE, DOUBLE],
+    [DOUBLE, NONE, NONE, LIGHT], [DOUBLE, NONE, NONE, DOUBLE], [LIGHT, DOUBLE, LIGHT, NONE], [DOUBLE, LIGHT, DOUBLE, NONE],
+    // ╠ ╡ ╢ ╣ ╤ ╥ ╦ ╧
+    [DOUBLE, DOUBLE, DOUBLE, NONE], [LIGHT, NONE, LIGHT, DOUBLE], [DOUBLE, NONE, DOUBLE, LIGHT], [DOUBLE, NONE, DOUBLE, DOUBLE],
+    [NONE, DOUBLE, LIGHT, DOUBLE], [NONE, LIGHT, DOUBLE, LIGHT], [NONE, DOUBLE, DOUBLE, DOUBLE], [LIGHT, DOUBLE, NONE, DOUBLE],
+    // ╨ ╩ ╪ ╫ ╬ ╭ ╮ ╯
+    [DOUBLE, LIGHT, NONE, LIGHT], [DOUBLE, DOUBLE, NONE, DOUBLE], [LIGHT, DOUBLE, LIGHT, DOUBLE], [DOUBLE, LIGHT, DOUBLE, LIGHT],
+    [DOUBLE, DOUBLE, DOUBLE, DOUBLE], [NONE, NONE, NONE, NONE], [NONE, NONE, NONE, NONE], [NONE, NONE, NONE, NONE],
+    // ╰ ╱ ╲ ╳ ╴ ╵ ╶ ╷
+    [NONE, NONE, NONE, NONE], [NONE, NONE, NONE, NONE], [NONE, NONE, NONE, NONE], [NONE, NONE, NONE, NONE],
+    [NONE, NONE, NONE, LIGHT], [LIGHT, NONE, NONE, NONE], [NONE, LIGHT, NONE, NONE], [NONE, NONE, LIGHT, NONE],
+    // ╸ ╹ ╺ ╻ ╼ ╽ ╾ ╿
+    [NONE, NONE, NONE, HEAVY], [HEAVY, NONE, NONE, NONE], [NONE, HEAVY, NONE, NONE], [NONE, NONE, HEAVY, NONE],
+    [NONE, HEAVY, NONE, LIGHT], [LIGHT, NONE, HEAVY, NONE], [NONE, LIGHT, NONE, HEAVY], [HEAVY, NONE, LIGHT, NONE],
+];
+
+/// Draw the character into a block of the given size, if it is one of the
+/// characters orbterm draws itself
+pub fn rasterize(c: char, width: usize, height: usize) -> Option<Glyph> {
+    if width == 0 || height == 0 {
+        return None;
+    }
+
+    let mut canvas = Canvas::new(width, height);
+    match c as u32 {
+        0x2504..=0x250B => {
+            canvas.lines(LINES[c as usize - 0x2500]);
+            // Triple dashes, then quadruple dashes
+            let dashes = if (c as u32) < 0x2508 { 3 } else { 4 };
+            canvas.dashes(dashes, (c as u32) % 4 < 2);
+        }
+        0x254C..=0x254F => {
+            canvas.lines(LINES[c as usize - 0x2500]);
+            canvas.dashes(2, (c as u32) < 0x254E);
+        }
+        0x256D => canvas.arc(1.0, 1.0),
+        0x256E => canvas.arc(-1.0, 1.0),
+        0x256F => canvas.arc(-1.0, -1.0),
+        0x2570 => canvas.arc(1.0, -1.0),
+        0x2571 => canvas.diagonal(false),
+        0x2572 => canvas.diagonal(true),
+        0x2573 => {
+            canvas.diagonal(false);
+            canvas.diagonal(true);
+        }
+        0x2500..=0x257F => canvas.lines(LINES[c as usize - 0x2500]),
+        0x2580..=0x259F => canvas.block_element(c),
+        0x2800..=0x28FF => canvas.braille(c as u32 as u8),
+        // Powerline
+        0xE0B0 => canvas.shape(|x, y| x <= 1.0 - (2.0 * y - 1.0).abs()),
+        0xE0B1 => canvas.chevron(false),
+        0xE0B2 => canvas.shape(|x, y| 1.0 - x <= 1.0 - (2.0 * y - 1.0).abs()),
+        0xE0B3 => canvas.chevron(true),
+        0xE0B4 => canvas.shape(|x, y| x * x + (2.0 * y - 1.0).powi(2) <= 1.0),
+        0xE0B6 => canvas.shape(|x, y| (1.0 - x).powi(2) + (2.0 * y - 1.0).powi(2) <= 1.0),
+        _ => return None,
+    }
+
+    Some(Glyph::from_coverage(
+        width,
+        height,
+        canvas.coverage.into_boxed_slice(),
+    ))
+}
+
+/// A half open range of pixels along one axis
+type Span = (usize, usize);
+
+/// The range of `thickness` pixels centred in `size` pixels
+fn band(size: usize, thickness: usize) -> Span {
+    let start = size.saturating_sub(thickness) / 2;
+    (start, cmp::min(size, start + thickness))
+}
+
+struct Canvas {
+    width: usize,
+    height: usize,
+    coverage: Vec<u8>,
+    /// The thickness of a light line, heavy lines are twice as thick and double
+    /// lines are two light lines with a light line of space between them
+    light: usize,
+}
+
+impl Canvas {
+    fn new(width: usize, height: usize) -> Canvas {
+        Canvas {
+            width,
+            height,
+            coverage: vec![0; width * height],
+            light: cmp::max(1, width / 8),
+        }
+    }
+
+    fn rect(&mut self, x: Span, y: Span, alpha: u8) {
+        for row in y.0..cmp::min(y.1, self.height) {
+            for column in x.0..cmp::min(x.1, self.width) {
+                self.coverage[row * self.width + column] = alpha;
+            }
+        }
+    }
+
+    /// Fill everything inside the shape, which is given coordinates relative to
+    /// the size of the block. Every pixel is sampled several times so that the
+    /// edges are smooth
+    fn shape<F: Fn(f32, f32) -> bool>(&mut self, inside: F) {
+        const SAMPLES: usize = 4;
+        for row in 0..self.height {
+            for column in 0..self.width {
+                let mut hits = 0;
+                for sample_y in 0..SAMPLES {
+                    for sample_x in 0..SAMPLES {
+                        let x = (column as f32 + (sample_x as f32 + 0.5) / SAMPLES as f32)
+                            / self.width as f32;
+                        let y = (row as f32 + (sample_y as f32 + 0.5) / SAMPLES as f32)
+                            / self.height as f32;
+                        if inside(x, y) {
+                            hits += 1;
+                        }
+                    }
+                }
+
+                let alpha = (hits * 255 / (SAMPLES * SAMPLES)) as u8;
+                let pixel = &mut self.coverage[row * self.width + column];
+                *pixel = cmp::max(*pixel, alpha);
+            }
+        }
+    }
+
+    fn thickness(&self, weight: u8) -> usize {
+        match weight {
+            HEAVY => self.light * 2,
+            _ => self.light,
+        }
+    }
+
+    /// Draw the lines going from the centre of the block towards its edges
+    fn lines(&mut self, [up, right, down, left]: [u8; 4]) {
+        let (width, height, light) = (self.width, self.height, self.light);
+
+        // Double lines are drawn as a thick line with the space between the
+        // two lines cut out of it, before any other lines are drawn
+        let horizontal = self.arms(width, [left, right], [up, down]);
+        let vertical = self.arms(height, [up, down], [left, right]);
+        let double_x = band(width, light * 3);
+        let double_y = band(height, light * 3);
+        for &(_, thick, _) in horizontal.iter().filter(|arm| arm.0 == DOUBLE) {
+            self.rect(thick, double_y, 0xFF);
+        }
+        for &(_, thick, _) in vertical.iter().filter(|arm| arm.0 == DOUBLE) {
+            self.rect(double_x, thick, 0xFF);
+        }
+        for &(_, _, thin) in horizontal.iter().filter(|arm| arm.0 == DOUBLE) {
+            self.rect(thin, band(height, light), 0);
+        }
+        for &(_, _, thin) in vertical.iter().filter(|arm| arm.0 == DOUBLE) {
+            self.rect(band(width, light), thin, 0);
+        }
+
+        for &(weight, extent, _) in horizontal
+            .iter()
+            .filter(|arm| arm.0 == LIGHT || arm.0 == HEAVY)
+        {
+            let y = band(height, self.thickness(weight));
+            self.rect(extent, y, 0xFF);
+        }
+        for &(weight, extent, _) in vertical
+            .iter()
+            .filter(|arm| arm.0 == LIGHT || arm.0 == HEAVY)
+        {
+            let x = band(width, self.thickness(weight));
+            self.rect(x, extent, 0xFF);
+        }
+    }
+
+    /// The extent of the two arms along one axis, which are given as the arm
+    /// towards the start and the arm towards the end of the axis. For double
+    /// lines, the extent of the space between the lines is given too
+    fn arms(&self, size: usize, [start, end]: [u8; 2], across: [u8; 2]) -> Vec<(u8, Span, Span)> {
+        let light = self.light;
+        let outer = band(size, light * 3);
+        let gap = band(size, light);
+
+        let across_double = across.contains(&DOUBLE);
+        // The thickest line crossing this axis, other than double lines
+        let across_single = across
+            .iter()
+            .filter(|&&weight| weight == LIGHT || weight == HEAVY)
+            .map(|&weight| self.thickness(weight))
+            .max();
+
+        let mut arms = Vec::new();
+        for &(weight, opposite, towards_start) in &[(start, end, true), (end, start, false)] {
+            // The part of the axis around the centre that the arm has to reach
+            let (reach, reach_gap) = match weight {
+                NONE => continue,
+                DOUBLE => {
+                    if across_double {
+                        (outer, gap)
+                    } else if let Some(thickness) = across_single {
+                        let line = band(size, thickness);
+                        (line, line)
+                    } else {
+                        (outer, outer)
+                    }
+                }
+                _ => {
+                    if across_double {
+                        // A single line ends at a double line going straight
+                        // through, but reaches both lines of a corner
+                        if across == [DOUBLE, DOUBLE] && opposite != LIGHT && opposite != HEAVY {
+                            let line = if towards_start {
+                                (outer.0, outer.0 + light)
+                            } else {
+                                (outer.1 - light, outer.1)
+                            };
+                            (line, line)
+                        } else {
+                            (outer, outer)
+                        }
+                    } else {
+                        let line = band(size, across_single.unwrap_or(self.thickness(weight)));
+                        (line, line)
+                    }
+                }
+            };
+
+            if towards_start {
+                arms.push((weight, (0, reach.1), (0, reach_gap.1)));
+            } else {
+                arms.push((weight, (reach.0, size), (reach_gap.0, size)));
+            }
+        }
+        arms
+    }
+
+    /// Cut gaps into the lines, so that they are drawn as dashes
+    fn dashes(&mut self, count: usize, horizontal: bool) {
+        let size = if horizontal { self.width } else { self.height };
+        for dash in 0..count {
+            let end = (dash + 1) * size / count;
+            let gap = cmp::max(1, size / count / 3);
+            let gap = (end.saturating_sub(gap), end);
+            if horizontal {
+                let height = self.height;
+                self.rect(gap, (0, height), 0);
+            } else {
+                let width = self.width;
+                self.rect((0, width), gap, 0);
+            }
+        }
+    }
+
+    /// A rounded corner joining the centre of the edges in the given directions
+    fn arc(&mut self, dx: f32, dy: f32) {
+        let (width, height) = (self.width as f32, self.height as f32);
+        let x = band(self.width, self.light);
+        let y = band(self.height, self.light);
+        let center_x = (x.0 + x.1) as f32 / 2.0;
+        let center_y = (y.0 + y.1) as f32 / 2.0;
+
+        // The circle touches both lines, with the largest radius that fits
+        let radius = if dx > 0.0 { width - center_x } else { center_x }.min(if dy > 0.0 {
+            height - center_y
+        } else {
+            center_y
+        });
+        let circle_x = center_x + dx * radius;
+        let circle_y = center_y + dy * radius;
+        let half = self.light as f32 / 2.0;
+
+        self.shape(|x, y| {
+            let (x, y) = (x * width, y * height);
+            // Only the quarter of the circle facing the centre of the block
+            if (x - circle_x) * dx > 0.0 || (y - circle_y) * dy > 0.0 {
+                return false;
+            }
+            let distance = ((x - circle_x).powi(2) + (y - circle_y).powi(2)).sqrt();
+            (distance - radius).abs() <= half
+        });
+
+        // Straight lines from the ends of the arc to the edges of the block
+        let arc_y = circle_y as usize;
+        if dy > 0.0 {
+            self.rect(x, (arc_y, self.height), 0xFF);
+        } else {
+            self.rect(x, (0, arc_y), 0xFF);
+        }
+        let arc_x = circle_x as usize;
+        if dx > 0.0 {
+            self.rect((arc_x, self.width), y, 0xFF);
+        } else {
+            self.rect((0, arc_x), y, 0xFF);
+        }
+    }
+
+    /// A line from corner to corner, going down to the right or up to the right
+    fn diagonal(&mut self, down: bool) {
+        let (width, height) = (self.width as f32, self.height as f32);
+        let length = (width * width + height * height).sqrt();
+        let half = self.light as f32 / 2.0;
+        self.shape(|x, y| {
+            let (x, y) = (x * width, y * height);
+            let distance = if down {
+                (x * height - y * width).abs()
+            } else {
+                (x * height + y * width - width * height).abs()
+            } / length;
+            distance <= half
+        });
+    }
+
+    /// The outline of a Powerline arrow, pointing right or left
+    fn chevron(&mut self, left: bool) {
+        let (width, height) = (self.width as f32, self.height as f32);
+        let half = self.light as f32 / 2.0;
+        self.shape(|x, y| {
+            let x = if left { 1.0 - x } else { x };
+            let (x, y) = (x * width, y * height);
+            // Distance from the line between the top left corner and the
+            // middle of the right edge, mirrored for the bottom half
+            let y = if y > height / 2.0 { height - y } else { y };
+            let length = (width * width + height * height / 4.0).sqrt();
+            ((x * height / 2.0 - y * width).abs() / length) <= half
+        });
+    }
+
+    /// U+2580 to U+259F
+    fn block_element(&mut self, c: char) {
+        let (width, height) = (self.width, self.height);
+        let eighths_x = |eighths: usize| (width * eighths + 4) / 8;
+        let eighths_y = |eighths: usize| (height * eighths + 4) / 8;
+        let (half_x, half_y) = (eighths_x(4), eighths_y(4));
+
+        let upper_left = [(0, half_x), (0, half_y)];
+        let upper_right = [(half_x, width), (0, half_y)];
+        let lower_left = [(0, half_x), (half_y, height)];
+        let lower_right = [(half_x, width), (half_y, height)];
+        let quadrants = |canvas: &mut Canvas, quadrants: &[[Span; 2]]| {
+            for quadrant in quadrants {
+                canvas.rect(quadrant[0], quadrant[1], 0xFF);
+            }
+        };
+
+        match c as u32 {
+            0x2580 => self.rect((0, width), (0, half_y), 0xFF),
+            // Lower eighths
+            n @ 0x2581..=0x2588 => {
+                let top = height - eighths_y(n as usize - 0x2580);
+                self.rect((0, width), (top, height), 0xFF)
+            }
+            // Left eighths
+            n @ 0x2589..=0x258F => {
+                let right = eighths_x(0x2590 - n as usize);
+                self.rect((0, right), (0, height), 0xFF)
+            }
+            0x2590 => self.rect((half_x, width), (0, height), 0xFF),
+            // Shades
+            n @ 0x2591..=0x2593 => {
+                let alpha = ((n - 0x2590) * 0x40) as u8;
+                self.rect((0, width), (0, height), alpha)
+            }
+            0x2594 => self.rect((0, width), (0, eighths_y(1)), 0xFF),
+            0x2595 => self.rect((width - eighths_x(1), width), (0, height), 0xFF),
+            0x2596 => quadrants(self, &[lower_left]),
+            0x2597 => quadrants(self, &[lower_right]),
+            0x2598 => quadrants(self, &[upper_left]),
+            0x2599 => quadrants(self, &[upper_left, lower_left, lower_right]),
+            0x259A => quadrants(self, &[upper_left, lower_right]),
+            0x259B => quadrants(self, &[upper_left, upper_right, lower_left]),
+            0x259C => quadrants(self, &[upper_left, upper_right, lower_right]),
+            0x259D => quadrants(self, &[upper_right]),
+            0x259E => quadrants(self, &[upper_right, lower_left]),
+            0x259F => quadrants(self, &[upper_right, lower_left, lower_right]),
+            _ => (),
+        }
+    }
+
+    /// A braille pattern, where every bit of the low byte of the character
+    /// stands for one of its eight dots
+    fn braille(&mut self, dots: u8) {
+        // The column and row of the dot for each bit
+        const DOTS: [(usize, usize); 8] = [
+            (0, 0),
+            (0, 1),
+            (0, 2),
+            (1, 0),
+            (1, 1),
+            (1, 2),
+            (0, 3),
+            (1, 3),
+        ];
+
+        let (width, height) = (self.width, self.height);
+        let size = cmp::max(1, cmp::min(width / 2, height / 4) / 2);
+        for (bit, &(column, row)) in DOTS.iter().enumerate() {
+            if dots & (1 << bit) != 0 {
+                let x = column * width / 2 + (width / 2).saturating_sub(size) / 2;
+                let y = row * height / 4 + (height / 4).saturating_sub(size) / 2;
+                self.rect((x, x + size), (y, y + size), 0xFF);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Block sizes with both odd and even widths and heights
+    const SIZES: [(usize, usize); 5] = [(5, 11), (7, 15), (8, 16), (9, 19), (10, 20)];
+
+    /// The rows covered in a column of the character
+    fn rows(c: char, width: usize, height: usize, x: usize) -> Vec<usize> {
+        let glyph = rasterize(c, width, height).unwrap();
+        (0..height).filter(|&y| glyph.alpha(x, y) != 0).collect()
+    }
+
+    /// The columns covered in a row of the character
+    fn columns(c: char, width: usize, height: usize, y: usize) -> Vec<usize> {
+        let glyph = rasterize(c, width, height).unwrap();
+        (0..width).filter(|&x| glyph.alpha(x, y) != 0).collect()
+    }
+
+    #[test]
+    fn horizontal_lines_reach_both_edges() {
+        for &(width, height) in &SIZES {
+            let left = rows('─', width, height, 0);
+            assert!(!left.is_empty());
+            for x in 1..width {
+                assert_eq!(rows('─', width, height, x), left, "{}x{}", width, height);
+            }
+        }
+    }
+
+    #[test]
+    fn lines_share_the_centre_band() {
+        for &(width, height) in &SIZES {
+            let horizontal = rows('─', width, height, 0);
+            let vertical = columns('│', width, height, 0);
+            assert_eq!(horizontal.len(), vertical.len(), "{}x{}", width, height);
+
+            // Lines joining at the centre of the block line up with the
+            // straight lines of the blocks next to them
+            for &c in &['┼', '┌', '┤'] {
+                let edge = if c == '┤' { 0 } else { width - 1 };
+                assert_eq!(rows(c, width, height, edge), horizontal, "{}", c);
+                assert_eq!(columns(c, width, height, height - 1), vertical, "{}", c);
+            }
+        }
+    }
+
+    #[test]
+    fn full_block_fills_the_block() {
+        for &(width, height) in &SIZES {
+            let glyph = rasterize('█', width, height).unwrap();
+            for y in 0..height {
+                for x in 0..width {
+                    assert_eq!(glyph.alpha(x, y), 0xFF);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn braille_dots_land_in_their_quarter() {
+        // The top left dot and the bottom right dot
+        for &(c, right, bottom) in &[('⠁', false, false), ('⢀', true, true)] {
+            for &(width, height) in &SIZES {
+                let glyph = rasterize(c, width, height).unwrap();
+                let mut covered = 0;
+                for y in 0..height {
+                    for x in 0..width {
+                        if glyph.alpha(x, y) != 0 {
+                            covered += 1;
+                            assert_eq!(x >= width / 2, right, "{} at {}x{}", c, width, height);
+                            if bottom {
+                                assert!(y >= height * 3 / 4, "{} at {}x{}", c, width, height);
+                            } else {
+                                assert!(y < height / 4, "{} at {}x{}", c, width, height);
+                            }
+                        }
+                    }
+                }
+                assert!(covered > 0);
+            }
+        }
+    }
+}
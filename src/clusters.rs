@@ -0,0 +1,86 @@
+//! Grapheme clusters of more than one character, such as accented letters made
+//! of a base and combining marks, or emoji joined with zero width joiners.
+//! Blocks refer to them by index, so that they can still be copied around freely.
+//! The URIs of hyperlinks are kept the same way
+
+use std::cmp;
+use std::collections::HashMap;
+use std::mem;
+
+/// How many entries a table holds before it is first compacted
+const MIN_LIMIT: usize = 1024;
+
+pub struct Clusters {
+    texts: Vec<String>,
+    indices: HashMap<String, u32>,
+    /// How many entries the table can hold before the ones that are no longer
+    /// used have to be dropped
+    limit: usize,
+}
+
+impl Default for Clusters {
+    fn default() -> Self {
+        Clusters {
+            texts: Vec::new(),
+            indices: HashMap::new(),
+            limit: MIN_LIMIT,
+        }
+    }
+}
+
+impl Clusters {
+    pub fn new() -> Self {
+        Clusters::default()
+    }
+
+    /// The index of the cluster, which is added if it has not been seen before.
+    /// Indices start at 1, so that 0 can stand for a lone character
+    pub fn intern(&mut self, text: String) -> u32 {
+        if let Some(&index) = self.indices.get(&text) {
+            return index;
+        }
+
+        self.texts.push(text.clone());
+        let index = self.texts.len() as u32;
+        self.indices.insert(text, index);
+        index
+    }
+
+    pub fn get(&self, index: u32) -> Option<&str> {
+        match index {
+            0 => None,
+            index => self.texts.get(index as usize - 1).map(String::as_str),
+        }
+    }
+
+    /// The highest index in use
+    pub fn len(&self) -> usize {
+        self.texts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.texts.is_empty()
+    }
+
+    /// Whether the table grew past its limit and should be compacted
+    pub fn is_full(&self) -> bool {
+        self.texts.len() > self.limit
+    }
+
+    /// Drop the entries whose index `used` is not set for, and return the new
+    /// index of every old one, which is 0 for the entries that were dropped.
+    /// The table can then grow to twice the size of what is left
+    pub fn compact(&mut self, used: &[bool]) -> Vec<u32> {
+        let texts = mem::take(&mut self.texts);
+        self.indices.clear();
+
+        let mut indices = vec![0; texts.len() + 1];
+        for (index, text) in texts.into_iter().enumerate() {
+            if used.get(index + 1) == Some(&true) {
+                indices[index + 1] = self.intern(text);
+            }
+        }
+        self.limit = cmp::max(MIN_LIMIT, self.texts.len() * 2);
+        indices
+    }
+}
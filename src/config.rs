@@ -1,6 +1,8 @@
 use console::ransid::Color;
 use failure::Error;
-use std::convert::{TryFrom, TryInto};
+use parser::CursorShape;
+use patterns::HintAction;
+use std::convert::TryInto;
 use std::error::Error as StdError;
 use std::fmt::Write;
 use std::fs::{self, File};
@@ -10,6 +12,8 @@ use std::path::{Path, PathBuf};
 use toml;
 use xdg::BaseDirectories;
 
+use DEFAULT_DPI;
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Hex(String);
 
@@ -68,7 +72,6 @@ impl From<Color> for Hex {
                     let gray = (value - 232) * 10 + 8;
                     encode_rgb(gray, gray, gray)
                 }
-                _ => encode_rgb(0, 0, 0),
             },
         })
     }
@@ -81,14 +84,126 @@ pub fn decode_hex(s: &str) -> Result<Vec<u8>, ParseIntError> {
         .collect()
 }
 
+/// A colour as X11 writes them, either `rgb:r/g/b` with one to four hex digits
+/// for each channel, or `#rrggbb`
+pub fn parse_color(spec: &str) -> Option<Color> {
+    if spec.starts_with('#') {
+        if spec.len() != 7 || !spec.is_ascii() {
+            return None;
+        }
+        return Hex(spec.to_string()).try_into().ok();
+    }
+
+    let mut channels = spec.strip_prefix("rgb:")?.split('/').map(|channel| {
+        if channel.is_empty() || channel.len() > 4 {
+            return None;
+        }
+        let value = u32::from_str_radix(channel, 16).ok()?;
+        let max = (1 << (4 * channel.len())) - 1;
+        Some((value * 0xFF / max) as u8)
+    });
+    let r = channels.next()??;
+    let g = channels.next()??;
+    let b = channels.next()??;
+    if channels.next().is_some() {
+        return None;
+    }
+    Some(Color::TrueColor(r, g, b))
+}
+
+/// A colour as `rgb:rrrr/gggg/bbbb`, which is how xterm reports colours
+pub fn color_spec(color: Color) -> String {
+    let Hex(hex) = Hex::from(color);
+    let rgb = decode_hex(&hex[1..]).unwrap_or_default();
+    let channels: Vec<String> = rgb
+        .iter()
+        .map(|channel| format!("{:02x}{:02x}", channel, channel))
+        .collect();
+    format!("rgb:{}", channels.join("/"))
+}
+
+/// Text matching `regex` is opened with `command` when it is Ctrl-clicked
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct OpenPattern {
+    pub regex: String,
+    /// Defaults to `link_opener`. Captured groups can be given to it as `$1` or
+    /// `${name}`, otherwise it is given the whole match as its last argument
+    pub command: Option<String>,
+}
+
+/// Text matching `regex` is labelled in hints mode, and copied or opened with
+/// `command` when its label is typed
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct HintPattern {
+    pub regex: String,
+    /// Defaults to copying the match
+    pub action: Option<HintAction>,
+    /// Defaults to `link_opener`, and is given captured groups the same way as
+    /// the command of an `OpenPattern`
+    pub command: Option<String>,
+}
+
 #[derive(Serialize, Deserialize, Clone)]
 pub struct Config {
     pub font: Option<String>,
     pub font_bold: Option<String>,
+    pub font_italic: Option<String>,
+    pub font_bold_italic: Option<String>,
+    pub font_fallback: Option<Vec<String>>,
     pub background_color: Option<Hex>,
+    /// How opaque the background is, from 0.0 to 1.0. Text is always opaque.
+    /// There is no option to blur what is behind the window, as orbclient
+    /// cannot ask the compositor for it
+    pub opacity: Option<f32>,
+    /// Whether blocks with a background colour other than the default one stay
+    /// opaque, whatever the opacity
+    pub opaque_colored_backgrounds: Option<bool>,
+    /// The size of the font in points
+    pub font_size: Option<f32>,
+    /// The resolution of the display in dots per inch, used to convert
+    /// `font_size` to pixels. Guessed from the size of the display when unset
+    pub dpi: Option<f32>,
+    /// How much each step of zooming in or out changes the font size by, as a
+    /// fraction of `font_size`
+    pub zoom_step: Option<f32>,
+    /// Whether zooming resizes the window to keep the same columns and rows,
+    /// rather than fitting more or less of them into the window
+    pub zoom_resizes_window: Option<bool>,
+    /// Multiplies the height of every row, 1.0 being the line height of the font
+    pub line_height: Option<f32>,
+    /// Pixels added to the width of every column
+    pub letter_spacing: Option<f32>,
+    pub cursor_shape: Option<CursorShape>,
+    pub cursor_blink: Option<bool>,
+    /// Whether everything is drawn faint while the window is not focused
+    pub dim_unfocused: Option<bool>,
+    pub padding_x: Option<u32>,
+    pub padding_y: Option<u32>,
+    /// Whether the grid is centred in the window, rather than placed in its top
+    /// left corner
+    pub center: Option<bool>,
+    /// The command hyperlinks are opened with when they are Ctrl-clicked, which
+    /// is given the URI as its last argument
+    pub link_opener: Option<String>,
+    /// How many rows are kept after they scroll off the top of the screen
+    pub scrollback_lines: Option<usize>,
+    /// The title of the window, which programs cannot change when it is set
+    pub title: Option<String>,
+    /// What the title of the window is made of, where `{title}` is the title
+    /// programs set, `{cwd}` the working directory the shell reported, and
+    /// `{cols}` and `{rows}` the size of the grid. Only the title when unset
+    pub title_template: Option<String>,
     pub save_scale: Option<bool>,
     pub columns: Option<u32>,
     pub rows: Option<u32>,
+    /// Text that can be opened with Ctrl-click besides hyperlinks. URLs,
+    /// `file:line:col` references and absolute paths when unset, which are
+    /// opened with `link_opener`, giving it only the file of references unless
+    /// it is an editor like vim, which is given `+line` before the file
+    pub open_patterns: Option<Vec<OpenPattern>>,
+    /// Text labelled in hints mode. URLs, git SHAs, IP addresses, paths and
+    /// numbers when unset
+    pub hint_patterns: Option<Vec<HintPattern>>,
 }
 
 impl Default for Config {
@@ -96,10 +211,33 @@ impl Default for Config {
         Config {
             font: None,
             font_bold: None,
+            font_italic: None,
+            font_bold_italic: None,
+            font_fallback: None,
             background_color: None,
+            opacity: None,
+            opaque_colored_backgrounds: None,
+            font_size: None,
+            dpi: None,
+            zoom_step: None,
+            zoom_resizes_window: None,
+            line_height: None,
+            letter_spacing: None,
+            cursor_shape: None,
+            cursor_blink: None,
+            dim_unfocused: None,
+            padding_x: None,
+            padding_y: None,
+            center: None,
+            link_opener: None,
+            scrollback_lines: None,
+            title: None,
+            title_template: None,
             save_scale: Some(true),
             columns: None,
             rows: None,
+            open_patterns: None,
+            hint_patterns: None,
         }
     }
 }
@@ -138,29 +276,91 @@ impl Config {
         file.write_all(contents.as_bytes()).map_err(Error::from)
     }
 
-    pub fn get_initial_scale(&self, display_height: u32) -> Result<f32, Error> {
-        let config_path = Config::get_config_path("scale")?;
-
-        let scale = (display_height / 1600) + 1;
+    /// The configured resolution, or a guess where high resolution displays are
+    /// assumed to be scaled by a whole factor
+    pub fn get_dpi(&self, display_height: u32) -> f32 {
+        self.dpi
+            .unwrap_or(DEFAULT_DPI * ((display_height / 1600) + 1) as f32)
+    }
 
+    /// The zoom the last window was left at, as a factor of the font size. The
+    /// scale older versions saved instead is converted to a zoom the first time
+    pub fn get_initial_zoom(&self, display_height: u32) -> Result<f32, Error> {
         if self.save_scale.is_some() && self.save_scale.unwrap() {
-            if config_path.exists() {
-                let mut file = File::open(&config_path)?;
-                let mut contents = String::new();
-                file.read_to_string(&mut contents)?;
-                let scale = contents.parse::<f32>()?;
-                return Ok(scale);
-            } else {
-                Config::set_initial_scale(scale as f32)?;
+            if let Some(zoom) = Config::read_saved("zoom")? {
+                return Ok(zoom);
             }
+
+            // The scale multiplied blocks of 8 by 16 pixels, the size the font is
+            // at with the default resolution and a zoom of 1
+            let zoom = match Config::read_saved("scale")? {
+                Some(scale) => scale * DEFAULT_DPI / self.get_dpi(display_height),
+                None => 1.0,
+            };
+            Config::set_initial_zoom(zoom)?;
+            return Ok(zoom);
+        }
+
+        Ok(1.0)
+    }
+
+    /// The number saved in the file, if there is one
+    fn read_saved(file_name: &str) -> Result<Option<f32>, Error> {
+        let config_path = Config::get_config_path(file_name)?;
+        if !config_path.exists() {
+            return Ok(None);
         }
 
-        Ok(scale as f32)
+        let mut file = File::open(&config_path)?;
+        let mut contents = String::new();
+        file.read_to_string(&mut contents)?;
+        Ok(Some(contents.trim().parse::<f32>()?))
     }
 
-    pub fn set_initial_scale(scale: f32) -> Result<(), Error> {
-        let config_path = Self::get_config_path("scale")?;
-        fs::write(&config_path, scale.to_string())?;
+    pub fn set_initial_zoom(zoom: f32) -> Result<(), Error> {
+        let config_path = Self::get_config_path("zoom")?;
+        fs::write(&config_path, zoom.to_string())?;
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rgb(spec: &str) -> Option<u32> {
+        parse_color(spec).map(|color| color.as_rgb() & 0xFFFFFF)
+    }
+
+    #[test]
+    fn parse_colors() {
+        assert_eq!(rgb("#ff8000"), Some(0xFF8000));
+        assert_eq!(rgb("rgb:ff/80/00"), Some(0xFF8000));
+        assert_eq!(rgb("rgb:ffff/8080/0000"), Some(0xFF8000));
+        assert_eq!(rgb("rgb:f/8/0"), Some(0xFF8800));
+        assert_eq!(rgb("rgb:fff/888/000"), Some(0xFF8800));
+
+        for spec in &[
+            "",
+            "red",
+            "#ff80",
+            "#ff8000ff",
+            "rgb:ff/80",
+            "rgb:ff/80/00/00",
+            "rgb:fffff/0/0",
+            "rgb:ff//00",
+            "rgb:gg/00/00",
+        ] {
+            assert_eq!(rgb(spec), None, "{}", spec);
+        }
+    }
+
+    #[test]
+    fn color_specs() {
+        assert_eq!(
+            color_spec(Color::TrueColor(0xFF, 0x80, 0x00)),
+            "rgb:ffff/8080/0000"
+        );
+        assert_eq!(rgb(&color_spec(Color::TrueColor(1, 2, 3))), Some(0x010203));
+    }
+}
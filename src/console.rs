@@ -1,68 +1,300 @@
 pub extern crate ransid;
 
-use std::collections::BTreeSet;
+use std::collections::VecDeque;
 use std::convert::TryInto;
 use std::io::Result;
-use std::ops::{Add, Deref};
-use std::{cmp, mem, ptr};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use std::{cmp, env, f32, mem, ptr};
 
 use config::Config;
-use orbclient::{Color, EventOption, Mode, Renderer, Window, WindowFlag};
-use orbfont::Font;
+use orbclient::{Color, EventOption, Mode, Window, WindowFlag};
 
 use crate::block_handler::BlockHandler;
-use crate::BLOCK_WIDTH;
-
-// Note that fonts can be located in either /usr/share/fonts/TTF or
-// /usr/share/fonts/truetype/ depending on the distro
-
-const FALLBACK_REGULAR_FONTS: [&'static str; 5] = [
-    "/usr/share/fonts/TTF/RobotoMono-Regular.ttf",
-    "/usr/share/fonts/TTF/DejaVuSansMono.ttf",
-    "/usr/share/fonts/truetype/dejavu/DejaVuSansMono.ttf",
-    "/usr/share/fonts/truetype/liberation/LiberationMono-Regular.ttf",
-    "/usr/share/fonts/truetype/ttf-dejavu/DejaVuSansMono.ttf",
-];
-
-const FALLBACK_BOLD_FONTS: [&'static str; 5] = [
-    "/usr/share/fonts/TTF/RobotoMono-Bold.ttf",
-    "/usr/share/fonts/TTF/DejaVuSansMono-Bold.ttf",
-    "/usr/share/fonts/truetype/dejavu/DejaVuSansMono.ttf",
-    "/usr/share/fonts/truetype/liberation/LiberationMono-Regular.ttf",
-    "/usr/share/fonts/truetype/ttf-dejavu/DejaVuSansMono.ttf",
-];
+use crate::clusters::Clusters;
+use crate::damage::Damage;
+use crate::fonts::Fonts;
+use crate::glyph_cache::{GlyphCache, GlyphKey};
+use crate::hints::{Hint, Hints};
+use crate::open;
+use crate::parser::{
+    self, CellColor, ColorSlot, CursorShape, Parser, PromptMark, Style, Underline,
+};
+use crate::patterns::{HintAction, Match, Pattern};
+use crate::render_target::RenderTarget;
+use crate::search::{Search, SearchMatch};
+use crate::vi::{ViMode, ViSelection};
+
+/// Output is drawn at most once per frame at 60 frames per second
+const FRAME_DURATION: Duration = Duration::from_micros(16_667);
+
+/// Blinking text is shown and hidden for this long in turn
+const BLINK_DURATION: Duration = Duration::from_millis(500);
+
+/// How opaque the background is, when no opacity is configured
+const DEFAULT_OPACITY: f32 = 224.0 / 255.0;
+
+/// How much the opacity changes with every press of Ctrl-Shift-Up or Down
+const OPACITY_STEP: f32 = 0.05;
+
+/// How many rows are kept after they scroll off the top of the grid, when no
+/// other number is configured
+const DEFAULT_SCROLLBACK_LINES: usize = 10_000;
+
+/// How many rows the mouse wheel scrolls the view by
+const WHEEL_LINES: usize = 3;
+
+/// Search matches are drawn black on yellow, and the current one on orange
+const MATCH_FG: CellColor = CellColor::Rgb(0x00, 0x00, 0x00);
+const MATCH_BG: CellColor = CellColor::Rgb(0xFF, 0xD7, 0x00);
+const CURRENT_MATCH_BG: CellColor = CellColor::Rgb(0xFF, 0x8C, 0x00);
+
+/// The title of the window until a program sets one
+const DEFAULT_TITLE: &str = "Terminal";
+/// How many titles the title stack holds, dropping the oldest beyond that
+const TITLE_STACK_SIZE: usize = 10;
+
+/// The row starts a prompt, the command typed at it, or the output of the
+/// command, as marked with OSC 133
+const MARK_PROMPT: u8 = 1;
+const MARK_COMMAND: u8 = 2;
+const MARK_OUTPUT: u8 = 4;
+/// The command started at the prompt on this row exited with an error
+const MARK_FAILED: u8 = 8;
+
+/// The colour of the mark in the first column of failed commands
+const FAILED_COLOR: Color = Color { data: 0xFFCD3131 };
 
 #[derive(Clone, Copy, Debug)]
 pub struct Block {
     c: char,
-    fg: Color,
-    bg: Color,
-    bold: bool,
+    /// The index of the grapheme cluster starting with `c` in
+    /// `Console::clusters`, or 0 when there is nothing combined with `c`
+    cluster: u32,
+    fg: CellColor,
+    bg: CellColor,
+    style: Style,
+    underline_color: Option<CellColor>,
+    /// The index of the URI the block links to in `Console::links`, or 0 when
+    /// it is not part of a hyperlink
+    link: u32,
+    /// The `MARK_*` flags of the row, kept in its first block so that they move
+    /// along with it
+    mark: u8,
+    /// How many blocks the character covers. This is 2 for wide characters and
+    /// 0 for the spacer behind them, which is drawn as part of the wide character
+    width: u8,
 }
 
-pub struct Console {
+/// Draw a colour at two thirds of its intensity, used for faint text
+fn dim(color: Color) -> Color {
+    let scale = |channel: u32| (color.data >> channel & 0xFF) * 2 / 3;
+    Color {
+        data: (color.data & 0xFF000000) | scale(16) << 16 | scale(8) << 8 | scale(0),
+    }
+}
+
+/// The path with the home directory shortened to ~
+fn short_path(path: &Path) -> String {
+    if let Some(home) = env::var_os("HOME") {
+        if let Ok(rest) = path.strip_prefix(&home) {
+            if rest.as_os_str().is_empty() {
+                return "~".to_string();
+            }
+            return format!("~/{}", rest.display());
+        }
+    }
+    path.display().to_string()
+}
+
+/// Replace every `{name}` in the template with its value. Names without a
+/// value are left as they are
+fn expand_title<F: Fn(&str) -> Option<String>>(template: &str, value: F) -> String {
+    let mut title = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        title.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let expanded = rest
+            .find('}')
+            .and_then(|end| value(&rest[1..end]).map(|value| (value, end)));
+        match expanded {
+            Some((value, end)) => {
+                title.push_str(&value);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                title.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+    title.push_str(rest);
+    title
+}
+
+/// Wide characters and their spacers are only ever drawn together, so a half
+/// that has lost its other half is turned back into a normal block
+fn repair_wide(grid: &mut [Block], w: usize, x: usize, y: usize, damage: &mut Damage) {
+    let i = y * w + x;
+    if x >= w || i >= grid.len() {
+        return;
+    }
+
+    match grid[i].width {
+        0 if x == 0 || grid[i - 1].width != 2 => {
+            grid[i].width = 1;
+            damage.add(x, y, 1, 1);
+        }
+        2 if x + 1 == w || grid[i + 1].width != 0 => {
+            grid[i].c = '\0';
+            grid[i].cluster = 0;
+            grid[i].style = Style::default();
+            grid[i].link = 0;
+            grid[i].width = 1;
+            damage.add(x, y, 2, 1);
+        }
+        _ => (),
+    }
+}
+
+/// A block of a hinted match, which has the rest of the label that has not been
+/// typed yet drawn over its start, and is underlined otherwise
+fn hint_block(block: Block, hint: &Hint, x: usize, typed: usize) -> Block {
+    match hint.label.chars().skip(typed).nth(x - hint.found.start) {
+        Some(c) => Block {
+            c,
+            cluster: 0,
+            style: Style {
+                bold: true,
+                inverse: !block.style.inverse,
+                ..Style::default()
+            },
+            underline_color: None,
+            ..block
+        },
+        None => Block {
+            style: Style {
+                underline: Underline::Single,
+                ..block.style
+            },
+            ..block
+        },
+    }
+}
+
+pub struct Console<T: RenderTarget = Window> {
     pub ransid: ransid::Console,
-    pub window: Window,
+    pub window: T,
     pub alternate: bool,
     pub grid: Box<[Block]>,
     pub alt_grid: Box<[Block]>,
-    pub font: Font,
-    pub font_bold: Font,
-    pub changed: BTreeSet<usize>,
+    pub parser: Parser,
+    pub fonts: Fonts,
+    pub glyph_cache: GlyphCache,
+    pub clusters: Clusters,
+    /// The URIs of hyperlinks, which blocks refer to by index
+    pub links: Clusters,
+    /// The link printed characters are part of
+    pub link: u32,
+    /// The link under the mouse, which is underlined
+    pub hovered_link: u32,
+    pub link_opener: String,
+    pub open_patterns: Vec<Pattern>,
+    /// The text under the mouse that Ctrl-click opens, which is underlined
+    /// while Ctrl is held
+    pub hovered_match: Option<Match>,
+    pub hint_patterns: Vec<Pattern>,
+    /// The labelled matches while in hints mode, during which keys pick a
+    /// match rather than being sent to the shell
+    pub hints: Option<Hints>,
+    /// Rows that scrolled off the top of the grid, oldest first
+    pub scrollback: VecDeque<Box<[Block]>>,
+    pub scrollback_lines: usize,
+    /// How many rows of the scrollback the view is scrolled back by
+    pub scroll_offset: usize,
+    /// The search bar while it is open, during which keys edit the query rather
+    /// than being sent to the shell
+    pub search: Option<Search>,
+    /// The cursor of copy mode while it is on, during which keys move it rather
+    /// than being sent to the shell
+    pub vi: Option<ViMode>,
+    /// Marks to apply again to the row of the next character printed
+    pub pending_mark: u8,
+    /// The title programs set, which the window title is made from
+    pub title: String,
+    /// Whether the title was configured, so that programs cannot change it
+    pub fixed_title: bool,
+    pub title_template: Option<String>,
+    /// Titles saved with `CSI 22 t`, to be restored with `CSI 23 t`
+    pub title_stack: Vec<String>,
+    /// The working directory the shell reported last with OSC 7
+    pub cwd: Option<PathBuf>,
+    pub damage: Damage,
+    pub cursor: Option<(usize, usize)>,
+    pub cursor_shape: CursorShape,
+    pub cursor_blink: bool,
+    /// The cursor the user configured, which applications can go back to
+    pub default_cursor: (CursorShape, bool),
+    /// The cursor stays visible for a while after it moves, so that it can be
+    /// followed while typing
+    pub cursor_blink_start: Instant,
+    pub cursor_visible: bool,
+    pub focused: bool,
+    pub dim_unfocused: bool,
+    pub last_frame: Instant,
+    pub blink_start: Instant,
+    pub blink_visible: bool,
     pub mouse_x: u16,
     pub mouse_y: u16,
     pub mouse_left: bool,
     pub ctrl: bool,
+    pub shift: bool,
     pub input: Vec<u8>,
+    #[allow(dead_code)]
     pub requested: usize,
     pub block_handler: BlockHandler,
+    pub zoom_resizes_window: bool,
     pub alpha: u8,
+    /// Whether blocks with a background colour other than the default one are
+    /// drawn opaque, whatever the opacity
+    pub opaque_colored_backgrounds: bool,
+    /// The blocks selected with the mouse, from where the selection started to
+    /// where it ends, as indexes of blocks counting from the oldest row of the
+    /// scrollback with every row as wide as the grid
     pub selection: Option<(usize, usize)>,
     pub last_selection: Option<(usize, usize)>,
+    #[allow(dead_code)]
     pub config: Config,
 }
 
 impl Console {
+    /// Open a window with room for the given number of columns and rows, on a
+    /// display with the given resolution
+    pub fn new(config: &Config, columns: u32, rows: u32, dpi: f32, zoom: f32) -> Console {
+        let fonts = Fonts::load(config);
+        let block_handler = BlockHandler::new(config, &fonts, dpi, zoom);
+        let (width, height) = block_handler.window_size(columns as usize, rows as usize);
+
+        let window = Window::new_flags(
+            -1,
+            -1,
+            width,
+            height,
+            DEFAULT_TITLE,
+            &[
+                WindowFlag::Async,
+                WindowFlag::Resizable,
+                WindowFlag::Transparent,
+            ],
+        )
+        .unwrap();
+
+        Console::with_fonts(config, window, fonts, block_handler)
+    }
+}
+
+impl<T: RenderTarget> Console<T> {
     pub fn input(&mut self, event_option: EventOption) {
         let mut next_selection = self.selection;
         match event_option {
@@ -71,24 +303,51 @@ impl Console {
 
                 if key_event.scancode == 0x1D {
                     self.ctrl = key_event.pressed;
+                    self.update_hover();
+                } else if key_event.scancode == orbclient::K_LEFT_SHIFT
+                    || key_event.scancode == orbclient::K_RIGHT_SHIFT
+                {
+                    self.shift = key_event.pressed;
                 } else if key_event.pressed {
                     match key_event.scancode {
+                        _ if self.hints.is_some() => {
+                            self.hint_key(key_event.scancode, key_event.character);
+                        }
+                        _ if self.search.as_ref().is_some_and(|search| search.typing) => {
+                            self.search_key(key_event.scancode, key_event.character);
+                        }
+                        _ if self.vi.is_some() => {
+                            self.vi_key(key_event.scancode, key_event.character);
+                        }
                         orbclient::K_0 if self.ctrl => {
                             // Ctrl-0 reset block size
-                            self.block_handler.reset_to_default();
-                            self.update_block_size();
+                            self.block_handler.reset_to_default(&self.fonts);
+                            self.zoomed();
                         }
                         orbclient::K_MINUS if self.ctrl => {
                             // Ctrl-Minus reduces the size of all the blocks on
                             // screen
-                            self.block_handler.increase_block_size(-1);
-                            self.update_block_size();
+                            self.block_handler.zoom_by(&self.fonts, -1.0);
+                            self.zoomed();
                         }
                         orbclient::K_EQUALS if self.ctrl => {
                             // Ctrl-Plus increases the size of all the blocks on
                             // screen
-                            self.block_handler.increase_block_size(1);
-                            self.update_block_size();
+                            self.block_handler.zoom_by(&self.fonts, 1.0);
+                            self.zoomed();
+                        }
+                        orbclient::K_SPACE if self.ctrl && self.shift => {
+                            // Ctrl-Shift-Space starts copy mode
+                            self.start_vi();
+                        }
+                        orbclient::K_UP if self.ctrl && self.shift => {
+                            // Ctrl-Shift-Up makes the background more opaque
+                            self.change_opacity(OPACITY_STEP);
+                        }
+                        orbclient::K_DOWN if self.ctrl && self.shift => {
+                            // Ctrl-Shift-Down makes the background more
+                            // transparent
+                            self.change_opacity(-OPACITY_STEP);
                         }
                         orbclient::K_BKSP => {
                             // Backspace
@@ -102,6 +361,16 @@ impl Console {
                             // Up
                             buf.extend_from_slice(b"\x1B[A");
                         }
+                        orbclient::K_PGUP if self.shift && !self.alternate => {
+                            // Shift-Page up scrolls the view back by a page
+                            let offset = self.scroll_offset + self.ransid.state.h;
+                            self.scroll_view(offset);
+                        }
+                        orbclient::K_PGDN if self.shift && !self.alternate => {
+                            // Shift-Page down scrolls the view forward by a page
+                            let offset = self.scroll_offset.saturating_sub(self.ransid.state.h);
+                            self.scroll_view(offset);
+                        }
                         orbclient::K_PGUP => {
                             // Page up
                             buf.extend_from_slice(b"\x1B[5~");
@@ -143,9 +412,41 @@ impl Console {
                                     self.window.set_clipboard(&text);
                                     '\0'
                                 }
+                                // Label what can be copied or opened with ctrl-shift-e
+                                'E' if self.ctrl => {
+                                    self.start_hints();
+                                    '\0'
+                                }
+                                // Go to the previous or the next prompt with
+                                // ctrl-shift-z and ctrl-shift-x
+                                'Z' if self.ctrl => {
+                                    self.jump_prompt(true);
+                                    '\0'
+                                }
+                                'X' if self.ctrl => {
+                                    self.jump_prompt(false);
+                                    '\0'
+                                }
+                                // Select the output of the last command with
+                                // ctrl-shift-g
+                                'G' if self.ctrl => {
+                                    self.select_last_output();
+                                    '\0'
+                                }
+                                // Open a new window in the same directory with
+                                // ctrl-shift-n
+                                'N' if self.ctrl => {
+                                    open::new_window(self.cwd.as_deref());
+                                    '\0'
+                                }
+                                // Search with ctrl-shift-f
+                                'F' if self.ctrl => {
+                                    self.start_search();
+                                    '\0'
+                                }
                                 // Paste with ctrl-shift-v
                                 'V' if self.ctrl => {
-                                    buf.extend_from_slice(&self.window.clipboard().as_bytes());
+                                    buf.extend_from_slice(self.window.clipboard().as_bytes());
                                     '\0'
                                 }
                                 c @ 'A'..='Z' if self.ctrl => ((c as u8 - b'A') + b'\x01') as char,
@@ -161,6 +462,10 @@ impl Console {
                     }
                 }
 
+                // Typing goes back to the bottom of the scrollback
+                if !buf.is_empty() {
+                    self.scroll_view(0);
+                }
                 self.input.extend(buf);
             }
             EventOption::Mouse(mouse_event) => {
@@ -169,14 +474,15 @@ impl Console {
                     .get_block_from_coordinate(mouse_event.x as usize, mouse_event.y as usize);
 
                 if self.ransid.state.mouse_rxvt {
-                    if self.ransid.state.mouse_btn {
-                        if self.mouse_left && (x != self.mouse_x || y != self.mouse_y) {
-                            let string = format!("\x1B[<{};{};{}M", 32, self.mouse_x, self.mouse_y);
-                            self.input.extend(string.as_bytes());
-                        }
+                    if self.ransid.state.mouse_btn
+                        && self.mouse_left
+                        && (x != self.mouse_x || y != self.mouse_y)
+                    {
+                        let string = format!("\x1B[<{};{};{}M", 32, self.mouse_x, self.mouse_y);
+                        self.input.extend(string.as_bytes());
                     }
                 } else if self.mouse_left {
-                    let i = (y as usize - 1) * self.ransid.state.w as usize + (x as usize - 1);
+                    let i = self.block_index(x, y);
                     next_selection = match self.selection {
                         Some(selection) => Some((selection.0, i)),
                         None => Some((i, i)),
@@ -184,11 +490,24 @@ impl Console {
                 }
                 self.mouse_x = x;
                 self.mouse_y = y;
+                self.update_hover();
             }
             EventOption::Button(button_event) => {
                 let x = self.mouse_x;
                 let y = self.mouse_y;
-                if self.ransid.state.mouse_rxvt {
+                let opening = self.ctrl
+                    && button_event.left
+                    && !self.mouse_left
+                    && (self.hovered_link != 0 || self.hovered_match.is_some());
+                if opening {
+                    // Ctrl-click opens the link or the text under the mouse,
+                    // instead of being reported or starting a selection
+                    if let Some(uri) = self.links.get(self.hovered_link) {
+                        open::open(&self.link_opener, uri, self.cwd.as_deref());
+                    } else if let Some(ref hovered_match) = self.hovered_match {
+                        open::spawn(&hovered_match.args, self.cwd.as_deref());
+                    }
+                } else if self.ransid.state.mouse_rxvt {
                     if button_event.left {
                         if !self.mouse_left {
                             let string = format!("\x1B[<{};{};{}M", 0, x, y);
@@ -199,19 +518,20 @@ impl Console {
                         self.input.extend(string.as_bytes());
                     }
                 } else if button_event.left && !self.mouse_left {
-                    let i = (y as usize - 1) * self.ransid.state.w as usize + (x as usize - 1);
+                    let i = self.block_index(x, y);
                     next_selection = Some((i, i));
                 }
 
-                self.mouse_left = button_event.left;
+                if !opening {
+                    self.mouse_left = button_event.left;
+                }
             }
             EventOption::Scroll(scroll_event) => {
                 if self.ctrl {
-                    let new_block_width =
-                        (self.block_handler.block_width as i32 + scroll_event.y.signum()) as usize;
-                    self.block_handler.set_block_size(new_block_width);
+                    self.block_handler
+                        .zoom_by(&self.fonts, scroll_event.y.signum() as f32);
 
-                    self.update_block_size();
+                    self.zoomed();
                 } else if self.ransid.state.mouse_rxvt {
                     if scroll_event.y > 0 {
                         let string = format!("\x1B[<{};{};{}M", 64, self.mouse_x, self.mouse_y);
@@ -220,16 +540,36 @@ impl Console {
                         let string = format!("\x1B[<{};{};{}M", 65, self.mouse_x, self.mouse_y);
                         self.input.extend(string.as_bytes());
                     }
+                } else if !self.alternate {
+                    let offset = if scroll_event.y > 0 {
+                        self.scroll_offset + WHEEL_LINES
+                    } else {
+                        self.scroll_offset.saturating_sub(WHEEL_LINES)
+                    };
+                    self.scroll_view(offset);
+                }
+            }
+            EventOption::Resize(_) => self.update_block_size(),
+            EventOption::Focus(focus_event) if focus_event.focused != self.focused => {
+                self.focused = focus_event.focused;
+                if self.parser.focus_reporting {
+                    let report = if self.focused { "\x1B[I" } else { "\x1B[O" };
+                    self.input.extend(report.as_bytes());
+                }
+
+                if self.dim_unfocused {
+                    self.damage.add_all();
+                } else if let Some((x, y)) = self.cursor {
+                    self.damage.add(x, y, 1, 1);
                 }
+                self.redraw();
             }
-            EventOption::Resize(resize_event) => self.update_block_size(),
             _ => (),
         }
 
         if next_selection != self.selection {
             self.selection = next_selection;
-            self.write(&[], true)
-                .expect("failed to write empty buffer after updating selection");
+            self.redraw();
         }
     }
 
@@ -267,22 +607,30 @@ impl Console {
         }
     }
 
-    pub fn new(
-        config: &Config,
-        width: u32,
-        height: u32,
-        block_width: usize,
-        block_height: usize,
-    ) -> Console {
-        let alpha = 224;
-        let cvt = |color: ransid::Color| -> Color {
-            Color {
-                data: ((alpha as u32) << 24) | (color.as_rgb() & 0xFFFFFF),
-            }
-        };
+    pub fn with_target(config: &Config, window: T, dpi: f32, zoom: f32) -> Console<T> {
+        let fonts = Fonts::load(config);
+        let block_handler = BlockHandler::new(config, &fonts, dpi, zoom);
+        Console::with_fonts(config, window, fonts, block_handler)
+    }
 
-        let mut ransid =
-            ransid::Console::new(width as usize / block_width, height as usize / block_height);
+    fn with_fonts(
+        config: &Config,
+        mut window: T,
+        fonts: Fonts,
+        mut block_handler: BlockHandler,
+    ) -> Console<T> {
+        let alpha =
+            (config.opacity.unwrap_or(DEFAULT_OPACITY).clamp(0.0, 1.0) * 255.0).round() as u8;
+        let link_opener = config
+            .link_opener
+            .clone()
+            .unwrap_or_else(|| open::DEFAULT_OPENER.to_string());
+        let open_patterns = Pattern::load(&config.open_patterns, &link_opener);
+        let hint_patterns = Pattern::load_hints(&config.hint_patterns, &link_opener);
+
+        let (w, h) =
+            block_handler.how_many_blocks_fit(window.width() as usize, window.height() as usize);
+        let mut ransid = ransid::Console::new(w, h);
 
         // Theming config
         if let Some(background) = &config.background_color {
@@ -290,410 +638,1765 @@ impl Console {
                 .clone()
                 .try_into()
                 .expect("Failed to convert background_color to a valid color");
-            ransid.state.background_default = ransid.state.background.clone();
+            ransid.state.background_default = ransid.state.background;
 
             println!("background: {:?}", background);
         }
 
-        let mut window = Window::new_flags(
-            -1,
-            -1,
-            width,
-            height,
-            "Terminal",
-            &[
-                WindowFlag::Async,
-                WindowFlag::Resizable,
-                WindowFlag::Transparent,
-            ],
-        )
-        .unwrap();
-        window.set(cvt(ransid.state.background));
+        window.set(Color {
+            data: ((alpha as u32) << 24) | (ransid.state.background.as_rgb() & 0xFFFFFF),
+        });
         window.sync();
 
         let grid = vec![
             Block {
                 c: '\0',
-                fg: cvt(ransid.state.foreground),
-                bg: cvt(ransid.state.background),
-                bold: false
+                cluster: 0,
+                fg: CellColor::Default,
+                bg: CellColor::Default,
+                style: Style::default(),
+                underline_color: None,
+                link: 0,
+                mark: 0,
+                width: 1
             };
             ransid.state.w * ransid.state.h
         ]
         .into_boxed_slice();
 
         let alt_grid = grid.clone();
+        let damage = Damage::new(ransid.state.w, ransid.state.h);
+        let default_cursor = (
+            config.cursor_shape.unwrap_or(CursorShape::Block),
+            config.cursor_blink.unwrap_or(false),
+        );
 
-        let font = if let Some(font_path) = &config.font {
-            Font::from_path(font_path).expect("Failed to load custom font")
-        } else {
-            let mut font = Font::find(Some("Mono"), None, Some("Regular"));
-
-            if font.is_err() {
-                // Try the fallback fonts
-                for temp_font in FALLBACK_REGULAR_FONTS.iter() {
-                    let temp_font = Font::from_path(temp_font);
-                    if temp_font.is_ok() {
-                        font = temp_font;
-                        break;
-                    };
-                }
-            }
-
-            font.expect("Could not find a regular monospace font")
-        };
-
-        let font_bold = if let Some(font_path) = &config.font_bold {
-            Font::from_path(font_path).expect("Failed to load custom bold font")
-        } else {
-            let mut font = Font::find(Some("Mono"), None, Some("Regular"));
-
-            if font.is_err() {
-                // Try the fallback fonts
-                for temp_font in FALLBACK_BOLD_FONTS.iter() {
-                    let temp_font = Font::from_path(temp_font);
-                    if temp_font.is_ok() {
-                        font = temp_font;
-                        break;
-                    };
-                }
-            }
-
-            font.expect("Could not find a bold monospace font")
-        };
-
-        Console {
+        let mut console = Console {
             ransid,
             window,
             alternate: false,
             grid,
             alt_grid,
-            font,
-            font_bold,
-            changed: BTreeSet::new(),
+            parser: Parser::new(),
+            fonts,
+            glyph_cache: GlyphCache::new(block_handler.metrics),
+            clusters: Clusters::new(),
+            links: Clusters::new(),
+            link: 0,
+            hovered_link: 0,
+            link_opener,
+            open_patterns,
+            hovered_match: None,
+            hint_patterns,
+            hints: None,
+            scrollback: VecDeque::new(),
+            scrollback_lines: config.scrollback_lines.unwrap_or(DEFAULT_SCROLLBACK_LINES),
+            scroll_offset: 0,
+            search: None,
+            vi: None,
+            pending_mark: 0,
+            title: config
+                .title
+                .clone()
+                .unwrap_or_else(|| DEFAULT_TITLE.to_string()),
+            fixed_title: config.title.is_some(),
+            title_template: config.title_template.clone(),
+            title_stack: Vec::new(),
+            cwd: None,
+            damage,
+            cursor: None,
+            cursor_shape: default_cursor.0,
+            cursor_blink: default_cursor.1,
+            default_cursor,
+            cursor_blink_start: Instant::now(),
+            cursor_visible: true,
+            focused: true,
+            dim_unfocused: config.dim_unfocused.unwrap_or(false),
+            last_frame: Instant::now(),
+            blink_start: Instant::now(),
+            blink_visible: true,
             mouse_x: 0,
             mouse_y: 0,
             mouse_left: false,
             ctrl: false,
+            shift: false,
             input: Vec::new(),
             requested: 0,
-            block_handler: BlockHandler::new(block_width, block_height),
+            block_handler,
+            zoom_resizes_window: config.zoom_resizes_window.unwrap_or(false),
             alpha,
+            opaque_colored_backgrounds: config.opaque_colored_backgrounds.unwrap_or(false),
             selection: None,
             last_selection: None,
             config: config.clone(),
-        }
+        };
+        console.update_title();
+        console
     }
 
+    /// Draw every damaged block from the grid and push the damaged area to the
+    /// window
     pub fn redraw(&mut self) {
-        /*
-        let width = self.window.width;
-        let height = self.window.height;
-        */
-        self.window.sync();
-        self.changed.clear();
-    }
+        let cursor = self.cursor_position();
+        if cursor != self.cursor {
+            if let Some((x, y)) = self.cursor {
+                self.damage.add(x, y, 1, 1);
+            }
+            if let Some((x, y)) = cursor {
+                self.damage.add(x, y, 1, 1);
+            }
+            self.cursor = cursor;
+            self.cursor_blink_start = Instant::now();
+        }
 
-    fn resize_grid(&mut self, w: usize, h: usize) {
-        let alpha = self.alpha;
-        let cvt = |color: ransid::Color| -> Color {
-            Color {
-                data: ((alpha as u32) << 24) | (color.as_rgb() & 0xFFFFFF),
+        let cursor_phase =
+            self.cursor_blink_start.elapsed().as_millis() / BLINK_DURATION.as_millis();
+        let cursor_visible = !self.cursor_blink || !self.focused || cursor_phase % 2 != 1;
+        if cursor_visible != self.cursor_visible {
+            self.cursor_visible = cursor_visible;
+            if let Some((x, y)) = self.cursor {
+                self.damage.add(x, y, 1, 1);
+            }
+        }
+
+        if self.selection != self.last_selection {
+            if let Some(selection) = self.last_selection {
+                self.damage_selection(selection);
+            }
+            if let Some(selection) = self.selection {
+                self.damage_selection(selection);
+            }
+            self.last_selection = self.selection;
+        }
+
+        let blink_phase = self.blink_start.elapsed().as_millis() / BLINK_DURATION.as_millis();
+        let blink_visible = blink_phase % 2 != 1;
+        if blink_visible != self.blink_visible {
+            self.blink_visible = blink_visible;
+            self.damage_blinking();
+        }
+
+        self.damage_wide();
+
+        if self.damage.is_empty() {
+            return;
+        }
+
+        let rows: Vec<(usize, usize, usize)> = self.damage.rows().collect();
+        for (y, start_x, end_x) in rows {
+            for x in start_x..end_x {
+                self.draw_block(x, y);
             }
+        }
+
+        if self.damage.is_full() {
+            self.window.sync();
+        } else {
+            let (block_width, block_height) = self.block_handler.get();
+            let rects: Vec<(i32, i32, u32, u32)> = self
+                .damage
+                .rects()
+                .into_iter()
+                .map(|(x, y, w, h)| {
+                    let (x, y) = self.block_handler.get_pixels_from_block(x, y);
+                    (
+                        x as i32,
+                        y as i32,
+                        (w * block_width) as u32,
+                        (h * block_height) as u32,
+                    )
+                })
+                .collect();
+            self.window.update_rects(&rects);
+        }
+
+        self.damage.clear();
+        self.last_frame = Instant::now();
+    }
+
+    fn draw_block(&mut self, x: usize, y: usize) {
+        let w = self.ransid.state.w;
+        let line = self.top_line() + y;
+        // The row of the grid, when the block is not part of the scrollback
+        let grid_y = line.checked_sub(self.scrollback.len());
+        let block = match self.line(line).get(x) {
+            Some(block) => *block,
+            // Rows of the scrollback may be narrower than the grid
+            None => Block {
+                c: '\0',
+                cluster: 0,
+                fg: CellColor::Default,
+                bg: CellColor::Default,
+                style: Style::default(),
+                underline_color: None,
+                link: 0,
+                mark: 0,
+                width: 1,
+            },
         };
 
-        if w != self.ransid.state.w || h != self.ransid.state.h {
-            let mut grid = vec![
-                Block {
-                    c: '\0',
-                    fg: cvt(self.ransid.state.foreground),
-                    bg: cvt(self.ransid.state.background),
-                    bold: false
-                };
-                w * h
-            ]
-            .into_boxed_slice();
+        // Hinted matches are underlined, with their labels drawn over them
+        let block = match (&self.hints, grid_y) {
+            (Some(hints), Some(grid_y)) => match hints.at(x, grid_y) {
+                Some(hint) => hint_block(block, hint, x, hints.typed.len()),
+                None => block,
+            },
+            _ => block,
+        };
 
-            let mut alt_grid = vec![
-                Block {
-                    c: '\0',
-                    fg: cvt(self.ransid.state.foreground),
-                    bg: cvt(self.ransid.state.background),
-                    bold: false
-                };
-                w * h
-            ]
-            .into_boxed_slice();
+        // Search matches are highlighted, and the search bar covers the last row
+        let search_bar = self.search.as_ref().is_some_and(|search| search.typing)
+            && y + 1 == self.ransid.state.h;
+        let block = match self.search {
+            Some(ref search) if search_bar => Block {
+                c: search.bar().chars().nth(x).unwrap_or('\0'),
+                cluster: 0,
+                style: Style {
+                    inverse: true,
+                    ..Style::default()
+                },
+                underline_color: None,
+                link: 0,
+                mark: 0,
+                width: 1,
+                ..block
+            },
+            Some(ref search) => match search.highlight(line, x) {
+                Some(current) => Block {
+                    fg: MATCH_FG,
+                    bg: if current { CURRENT_MATCH_BG } else { MATCH_BG },
+                    style: Style {
+                        inverse: false,
+                        ..block.style
+                    },
+                    ..block
+                },
+                None => block,
+            },
+            None => block,
+        };
+
+        // Spacers are covered by the wide character before them
+        if block.width == 0 {
+            return;
+        }
 
-            self.window.set(cvt(self.ransid.state.background));
+        let (pixel_x, pixel_y) = self.block_handler.get_pixels_from_block(x, y);
+        let (block_width, block_height) = self.block_handler.get();
+        let cells = block.width as usize;
+        let width = block_width * cells;
 
+        let cursor = match grid_y {
+            Some(grid_y) if !search_bar => (x..x + cells).any(|x| self.cursor == Some((x, grid_y))),
+            _ => false,
+        };
+        // A block cursor with a colour of its own is drawn in that colour, with
+        // the text in the background colour on top of it
+        let cursor_color = match self.parser.cursor_color {
+            Some(color)
+                if cursor
+                    && self.cursor_visible
+                    && self.focused
+                    && self.cursor_shape == CursorShape::Block =>
             {
-                let font = &self.font;
-                let font_bold = &self.font_bold;
-                let window = &mut self.window;
-                let mut str_buf = [0; 4];
+                Some(Color {
+                    data: 0xFF000000 | color.as_rgb(),
+                })
+            }
+            _ => None,
+        };
+
+        let (fg, bg) = (self.color(block.fg, false), self.color(block.bg, true));
+        let (fg, bg) = if block.style.inverse {
+            (bg, fg)
+        } else {
+            (fg, bg)
+        };
+        let (fg, bg) = match cursor_color {
+            Some(color) => (bg, color),
+            None => (fg, bg),
+        };
+        let fg = if block.style.dim { dim(fg) } else { fg };
+        let (fg, bg) = if self.dim_unfocused && !self.focused {
+            (dim(fg), dim(bg))
+        } else {
+            (fg, bg)
+        };
 
-                for y in 0..self.ransid.state.h {
-                    for x in 0..self.ransid.state.w {
-                        let block = self.grid[y * self.ransid.state.w + x];
-                        if y < h && x < w {
-                            grid[y * w + x] = block;
+        // Only the background is see-through, text always stays opaque
+        let colored =
+            block.style.inverse || block.bg != CellColor::Default || cursor_color.is_some();
+        let bg = if self.opaque_colored_backgrounds && colored {
+            bg
+        } else {
+            Color {
+                data: ((self.alpha as u32) << 24) | (bg.data & 0xFFFFFF),
+            }
+        };
 
-                            let alt_block = self.alt_grid[y * self.ransid.state.w + x];
-                            alt_grid[y * w + x] = alt_block;
-                        }
+        self.window.mode().set(Mode::Overwrite);
+        self.window.rect(
+            pixel_x as i32,
+            pixel_y as i32,
+            width as u32,
+            block_height as u32,
+            bg,
+        );
+        self.window.mode().set(Mode::Blend);
+
+        // Failed commands have a line down the side of their prompt
+        if x == 0 && block.mark & MARK_FAILED != 0 {
+            self.window.rect(
+                pixel_x as i32,
+                pixel_y as i32,
+                cmp::max(1, block_width / 8) as u32,
+                block_height as u32,
+                FAILED_COLOR,
+            );
+        }
 
-                        let (x, y) = self.block_handler.get_pixels_from_block(x, y);
-                        let (block_width, block_height) = self.block_handler.get();
+        let visible = !block.style.hidden && (!block.style.blink || self.blink_visible);
+        if visible {
+            if block.c != '\0' {
+                let key = GlyphKey {
+                    c: block.c,
+                    cluster: block.cluster,
+                    bold: block.style.bold,
+                    italic: block.style.italic,
+                };
+                let mut str_buf = [0; 4];
+                let text = match self.clusters.get(block.cluster) {
+                    Some(text) => text,
+                    None => block.c.encode_utf8(&mut str_buf),
+                };
+                let glyph = self.glyph_cache.get(&mut self.fonts, text, key);
+                // Wide characters are centred over both of their blocks
+                let offset = if cells > 1 {
+                    width.saturating_sub(glyph.width()) / 2
+                } else {
+                    0
+                };
+                glyph.draw(
+                    &mut self.window,
+                    (pixel_x + offset) as i32,
+                    pixel_y as i32,
+                    fg,
+                );
+            }
 
-                        window.mode().set(Mode::Overwrite);
-                        window.rect(
-                            x as i32,
-                            y as i32,
-                            block_width as u32,
-                            block_height as u32,
-                            block.bg,
-                        );
-                        window.mode().set(Mode::Blend);
-
-                        if block.c != '\0' {
-                            if block.bold {
-                                font_bold
-                                    .render(&block.c.encode_utf8(&mut str_buf), block_height as f32)
-                                    .draw(window, x as i32, y as i32, block.fg);
-                            } else {
-                                font.render(
-                                    &block.c.encode_utf8(&mut str_buf),
-                                    block_height as f32,
-                                )
-                                .draw(window, x as i32, y as i32, block.fg);
-                            }
-                        }
-                    }
-                    self.changed.insert(y as usize);
+            // Lines are drawn opaque, the same as the glyphs
+            let line_color = Color {
+                data: fg.data | 0xFF000000,
+            };
+            let thickness = cmp::max(1, block_height / 16);
+            // Links, and text that can be opened while Ctrl is held, are
+            // underlined while the mouse is over them, unless they already are
+            let hovered = (block.link != 0 && block.link == self.hovered_link)
+                || self.hovered_match.as_ref().is_some_and(|hovered| {
+                    Some(hovered.y) == grid_y && hovered.start <= x && x < hovered.end
+                });
+            let underline = match block.style.underline {
+                Underline::None if hovered => Underline::Single,
+                underline => underline,
+            };
+            if underline != Underline::None {
+                let underline_color = match block.underline_color {
+                    Some(color) => self.color(color, false),
+                    None => line_color,
+                };
+                for cell in 0..cells {
+                    self.draw_underline(
+                        pixel_x + cell * block_width,
+                        pixel_y,
+                        underline,
+                        underline_color,
+                    );
                 }
             }
+            if block.style.strikethrough {
+                self.window.rect(
+                    pixel_x as i32,
+                    (pixel_y + block_height / 2) as i32,
+                    width as u32,
+                    thickness as u32,
+                    line_color,
+                );
+            }
+        }
 
-            self.ransid.resize(w, h);
-            self.grid = grid;
-            self.alt_grid = alt_grid;
+        let selected = !search_bar && {
+            let i = line * w + x;
+            (i..i + cells).any(|i| self.is_selected(i))
+        };
+        let vi_selected = self
+            .vi
+            .as_ref()
+            .is_some_and(|vi| !search_bar && (x..x + cells).any(|x| vi.selects(line, x, w)));
+        if selected || vi_selected {
+            self.invert(pixel_x, pixel_y, width, block_height);
+        }
 
-            if self.ransid.state.cursor
-                && self.ransid.state.x < self.ransid.state.w
-                && self.ransid.state.y < self.ransid.state.h
-            {
-                let (x, y) = self
-                    .block_handler
-                    .get_pixels_from_block(self.ransid.state.x, self.ransid.state.y);
+        // The cursor of copy mode is outlined, so that it can be seen on top of
+        // the selection
+        let vi_cursor = self
+            .vi
+            .as_ref()
+            .is_some_and(|vi| !search_bar && vi.line == line && x <= vi.x && vi.x < x + cells);
+        if vi_cursor {
+            self.draw_outline(pixel_x, pixel_y, width);
+        }
 
-                let (block_width, block_height) = self.block_handler.get();
+        if self.cursor_visible && cursor && cursor_color.is_none() {
+            self.draw_cursor(pixel_x, pixel_y, width);
+        }
+    }
 
-                self.invert(x, y, block_width, block_height);
-            }
+    /// Invert the edges of a block
+    fn draw_outline(&mut self, pixel_x: usize, pixel_y: usize, width: usize) {
+        let (block_width, block_height) = self.block_handler.get();
+        let thickness = cmp::max(1, block_width / 8);
+
+        let side_height = block_height.saturating_sub(2 * thickness);
+        let bottom_y = pixel_y + block_height - thickness;
+        let right_x = pixel_x + width - thickness;
+        self.invert(pixel_x, pixel_y, width, thickness);
+        self.invert(pixel_x, bottom_y, width, thickness);
+        self.invert(pixel_x, pixel_y + thickness, thickness, side_height);
+        self.invert(right_x, pixel_y + thickness, thickness, side_height);
+    }
 
-            //TODO: Figure out what should happen on resize
-            self.selection = None;
+    /// Draw the cursor by inverting part of the block under it, or its outline
+    /// when the window is not focused
+    fn draw_cursor(&mut self, pixel_x: usize, pixel_y: usize, width: usize) {
+        let (block_width, block_height) = self.block_handler.get();
+        let thickness = cmp::max(1, block_width / 8);
+
+        if !self.focused {
+            self.draw_outline(pixel_x, pixel_y, width);
+            return;
+        }
+
+        let (y, width, height) = match self.cursor_shape {
+            CursorShape::Block => (pixel_y, width, block_height),
+            CursorShape::Underline => {
+                let height = cmp::max(1, block_height / 8);
+                (pixel_y + block_height - height, width, height)
+            }
+            CursorShape::Bar => (pixel_y, thickness, block_height),
+        };
+        match self.parser.cursor_color {
+            Some(color) => self.window.rect(
+                pixel_x as i32,
+                y as i32,
+                width as u32,
+                height as u32,
+                Color {
+                    data: 0xFF000000 | color.as_rgb(),
+                },
+            ),
+            None => self.invert(pixel_x, y, width, height),
         }
     }
 
-    pub fn selection_text(&self) -> String {
-        let mut string = String::new();
-        if let Some(selection) = self.selection {
-            let mut skipping = false;
-            for i in cmp::min(selection.0, selection.1)..cmp::max(selection.0, selection.1) {
-                if let Some(block) = self.grid.get(i) {
-                    if block.c == '\0' {
-                        skipping = true;
-                    } else {
-                        if skipping {
-                            string.push('\n');
-                            skipping = false;
-                        }
-                        string.push(block.c);
+    fn draw_underline(
+        &mut self,
+        pixel_x: usize,
+        pixel_y: usize,
+        underline: Underline,
+        color: Color,
+    ) {
+        let (block_width, block_height) = self.block_handler.get();
+        let thickness = cmp::max(1, block_height / 16);
+        let x = pixel_x as i32;
+        let y = (pixel_y + block_height - 2 * thickness) as i32;
+        let t = thickness as i32;
+
+        match underline {
+            Underline::None => (),
+            Underline::Single => {
+                self.window
+                    .rect(x, y, block_width as u32, thickness as u32, color);
+            }
+            Underline::Double => {
+                self.window
+                    .rect(x, y - t, block_width as u32, thickness as u32, color);
+                self.window
+                    .rect(x, y + t, block_width as u32, thickness as u32, color);
+            }
+            Underline::Curly => {
+                // One period of a sine wave per block, so that the waves of
+                // neighbouring blocks join up
+                for dx in 0..block_width {
+                    let phase = dx as f32 / block_width as f32 * 2.0 * f32::consts::PI;
+                    let dy = (phase.sin() * t as f32).round() as i32;
+                    self.window
+                        .rect(x + dx as i32, y - dy, 1, thickness as u32, color);
+                }
+            }
+            Underline::Dotted => {
+                for dot in 0..block_width / thickness {
+                    if dot % 2 == 0 {
+                        self.window.rect(
+                            x + (dot * thickness) as i32,
+                            y,
+                            thickness as u32,
+                            thickness as u32,
+                            color,
+                        );
                     }
                 }
             }
+            Underline::Dashed => {
+                // Two dashes per block, spaced as far apart as they are long
+                let dash = cmp::max(1, block_width / 4);
+                for start in &[dash / 2, dash * 5 / 2] {
+                    self.window
+                        .rect(x + *start as i32, y, dash as u32, thickness as u32, color);
+                }
+            }
         }
-        string
     }
 
-    fn set_selection(&mut self, i: usize) {
-        let (x, y) = self
-            .block_handler
-            .get_pixels_from_block(i % self.ransid.state.w, i / self.ransid.state.w);
-        let (block_width, block_height) = self.block_handler.get();
-        self.invert(x, y, block_width, block_height);
-        self.changed.insert(y as usize);
+    /// Wide characters are drawn together with their spacer, so when either one
+    /// is damaged, both have to be
+    fn damage_wide(&mut self) {
+        let top = self.top_line();
+        let rows: Vec<(usize, usize, usize)> = self.damage.rows().collect();
+        for (y, start_x, end_x) in rows {
+            let row = self.line(top + y);
+            let spacer = start_x > 0 && row.get(start_x).is_some_and(|block| block.width == 0);
+            let wide = row.get(end_x - 1).is_some_and(|block| block.width == 2);
+            if spacer {
+                self.damage.add(start_x - 1, y, 1, 1);
+            }
+            if wide {
+                self.damage.add(end_x, y, 1, 1);
+            }
+        }
     }
 
-    fn sync(&mut self) {
-        if !self.changed.is_empty() {
-            self.window.sync();
-        }
-        self.changed.clear();
+    /// The text of a row of the grid, with the column every byte of it is in and
+    /// the width of the row at the end
+    pub fn row_text(&self, y: usize) -> (String, Vec<usize>) {
+        self.line_text(self.scrollback.len() + y)
     }
 
-    pub fn update_block_size(&mut self) {
-        let (w, h) = self
-            .block_handler
-            .how_many_blocks_fit(self.window.width() as usize, self.window.height() as usize);
+    /// The text of a line of the scrollback or the grid, the same as `row_text`
+    pub fn line_text(&self, line: usize) -> (String, Vec<usize>) {
+        let blocks = self.line(line);
+        let mut text = String::new();
+        let mut columns = Vec::new();
+        for (x, block) in blocks.iter().enumerate() {
+            // Spacers are part of the wide character before them
+            if block.width == 0 {
+                continue;
+            }
 
-        self.resize_grid(w, h);
-        self.sync();
+            let start = text.len();
+            match self.clusters.get(block.cluster) {
+                Some(cluster) => text.push_str(cluster),
+                None if block.c == '\0' => text.push(' '),
+                None => text.push(block.c),
+            }
+            columns.resize(columns.len() + text.len() - start, x);
+        }
+        columns.push(blocks.len());
+
+        (text, columns)
     }
 
-    pub fn write(&mut self, buf: &[u8], sync: bool) -> Result<usize> {
-        let alpha = self.alpha;
-        let cvt = |color: ransid::Color| -> Color {
-            Color {
-                data: ((alpha as u32) << 24) | (color.as_rgb() & 0xFFFFFF),
-            }
+    /// Find the link, or with Ctrl held the text that can be opened, under the
+    /// mouse, and damage what changed so that its underline is drawn or cleared
+    fn update_hover(&mut self) {
+        let x = self.mouse_x as usize;
+        let y = self.mouse_y as usize;
+        let w = self.ransid.state.w;
+        let h = self.ransid.state.h;
+        // Only the grid has links to open, not the scrollback
+        let inside = x >= 1 && y >= 1 && x <= w && y <= h && self.scroll_offset == 0;
+
+        let hovered_link = if inside {
+            self.grid[(y - 1) * w + (x - 1)].link
+        } else {
+            0
+        };
+        let hovered_match = if inside && self.ctrl && hovered_link == 0 {
+            let (text, columns) = self.row_text(y - 1);
+            self.open_patterns
+                .iter()
+                .find_map(|pattern| pattern.find(&text, &columns, y - 1, x - 1))
+        } else {
+            None
         };
 
-        if let Some(selection) = self.last_selection {
-            for i in cmp::min(selection.0, selection.1)..cmp::max(selection.0, selection.1) {
-                self.set_selection(i);
-            }
+        if hovered_link == self.hovered_link && hovered_match == self.hovered_match {
+            return;
         }
 
-        if self.ransid.state.cursor
-            && self.ransid.state.x < self.ransid.state.w
-            && self.ransid.state.y < self.ransid.state.h
-        {
-            let (x, y) = self
-                .block_handler
-                .get_pixels_from_block(self.ransid.state.x, self.ransid.state.y);
+        let previous = mem::replace(&mut self.hovered_link, hovered_link);
+        self.damage_link(previous);
+        self.damage_link(hovered_link);
 
-            let (block_width, block_height) = self.block_handler.get();
+        let previous = mem::replace(&mut self.hovered_match, hovered_match);
+        for hovered in previous.iter().chain(self.hovered_match.iter()) {
+            self.damage
+                .add(hovered.start, hovered.y, hovered.end - hovered.start, 1);
+        }
 
-            self.invert(x, y, block_width, block_height);
-            self.changed.insert(y);
+        self.redraw();
+    }
+
+    /// A row of the scrollback or the grid, counting from the oldest row of the
+    /// scrollback
+    pub fn line(&self, line: usize) -> &[Block] {
+        match self.scrollback.get(line) {
+            Some(row) => row,
+            None => {
+                let w = self.ransid.state.w;
+                let y = line - self.scrollback.len();
+                &self.grid[y * w..(y + 1) * w]
+            }
         }
+    }
+
+    /// The line shown in the top row of the view
+    fn top_line(&self) -> usize {
+        self.scrollback.len() - self.scroll_offset
+    }
+
+    /// The index of the block under the mouse, in the scrollback or the grid,
+    /// as it is kept in `selection`. The mouse may be in the padding or the
+    /// margin around the grid, past its last column or row
+    fn block_index(&self, x: u16, y: u16) -> usize {
+        let state = &self.ransid.state;
+        let x = cmp::min(x as usize, state.w).saturating_sub(1);
+        let y = cmp::min(y as usize, state.h).saturating_sub(1);
+        (self.top_line() + y) * state.w + x
+    }
+
+    /// The block at an index of `selection`, which is past the end of rows of
+    /// the scrollback that are narrower than the grid
+    fn selected_block(&self, i: usize) -> Option<&Block> {
+        let w = self.ransid.state.w;
+        if i / w < self.scrollback.len() + self.ransid.state.h {
+            self.line(i / w).get(i % w)
+        } else {
+            None
+        }
+    }
+
+    /// Scroll the view back by the given number of rows, up to the start of the
+    /// scrollback
+    fn scroll_view(&mut self, offset: usize) {
+        let offset = cmp::min(offset, self.scrollback.len());
+        if offset == self.scroll_offset {
+            return;
+        }
+
+        self.scroll_offset = offset;
+        self.hovered_link = 0;
+        self.hovered_match = None;
+        self.damage.add_all();
+        self.redraw();
+    }
+
+    /// Scroll the view so that the line can be seen, above the search bar when
+    /// it is open
+    fn scroll_to_line(&mut self, line: usize) {
+        let top = self.top_line();
+        let rows = if self.search.as_ref().is_some_and(|search| search.typing) {
+            self.ransid.state.h.saturating_sub(1)
+        } else {
+            self.ransid.state.h
+        };
+        if line < top || line >= top + rows {
+            let top = cmp::min(line.saturating_sub(rows / 2), self.scrollback.len());
+            self.scroll_view(self.scrollback.len() - top);
+        }
+    }
+
+    /// Open the search bar, keeping the last query if it is already open
+    fn start_search(&mut self) {
+        self.search.get_or_insert_with(Search::default).typing = true;
+        self.damage.add_all();
+        self.redraw();
+    }
+
+    /// Find the matches of the query in the lines from `from` on, keeping the
+    /// matches in the lines before it, which are unchanged other than moving up
+    /// as `dropped` lines were dropped from the start of the scrollback
+    fn update_search(&mut self, from: usize, dropped: usize) {
+        let regex = match self.search {
+            Some(ref search) => search.compile(),
+            None => return,
+        };
+
+        let mut matches = Vec::new();
+        if let Some(regex) = regex {
+            for line in from..self.scrollback.len() + self.ransid.state.h {
+                let (text, columns) = self.line_text(line);
+                for found in regex.find_iter(&text) {
+                    if found.start() < found.end() {
+                        matches.push(SearchMatch {
+                            line,
+                            start: columns[found.start()],
+                            end: columns[found.end()],
+                        });
+                    }
+                }
+            }
+        }
+
+        if let Some(ref mut search) = self.search {
+            let moved = |found: &SearchMatch| SearchMatch {
+                line: found.line - dropped,
+                ..*found
+            };
+            let current = search
+                .current
+                .map(|current| search.matches[current])
+                .filter(|found| found.line >= dropped)
+                .map(|found| moved(&found));
+            let kept = search
+                .matches
+                .iter()
+                .filter(|found| found.line >= dropped && found.line < from + dropped)
+                .map(moved);
+            search.matches = kept.chain(matches).collect();
+            // The current match stays current when it is still there
+            search.current =
+                current.and_then(|current| search.matches.binary_search(&current).ok());
+        }
+
+        // The rows of the lines that were searched, and the count of matches in
+        // the search bar, are drawn again
+        let (w, h) = (self.ransid.state.w, self.ransid.state.h);
+        let start = from.saturating_sub(self.top_line());
+        if start < h {
+            self.damage.add(0, start, w, h - start);
+        }
+        self.damage.add(0, h - 1, w, 1);
+    }
+
+    /// Move to the match before the current one, or the one after it, wrapping
+    /// around at either end. The last match is the first one moved to
+    fn jump_search(&mut self, back: bool) {
+        let line = match self.search {
+            Some(ref mut search) if !search.matches.is_empty() => {
+                let len = search.matches.len();
+                let current = match search.current {
+                    Some(current) if back => (current + len - 1) % len,
+                    Some(current) => (current + 1) % len,
+                    None => len - 1,
+                };
+                search.current = Some(current);
+                search.matches[current].line
+            }
+            _ => return,
+        };
+
+        self.damage.add_all();
+        self.scroll_to_line(line);
+    }
+
+    /// Typing edits the query, Enter moves to the match above and Shift-Enter to
+    /// the one below, Ctrl-R switches between plain text and regexes, Ctrl-I
+    /// switches case sensitivity and Escape closes the search bar
+    fn search_key(&mut self, scancode: u8, character: char) {
+        let ctrl = self.ctrl;
+        let mut changed = false;
+        match scancode {
+            orbclient::K_ESC => self.search = None,
+            orbclient::K_ENTER if self.vi.is_some() => {
+                // Copy mode moves to the match after its cursor, and keeps the
+                // matches to move between with n and N
+                if let Some(ref mut search) = self.search {
+                    search.typing = false;
+                }
+                self.vi_jump(false);
+            }
+            orbclient::K_ENTER => {
+                let back = !self.shift;
+                self.jump_search(back);
+            }
+            _ => {
+                if let Some(ref mut search) = self.search {
+                    changed = match character {
+                        _ if scancode == orbclient::K_BKSP => search.query.pop().is_some(),
+                        'r' | 'R' if ctrl => {
+                            search.regex = !search.regex;
+                            true
+                        }
+                        'i' | 'I' if ctrl => {
+                            search.case_sensitive = !search.case_sensitive;
+                            true
+                        }
+                        c if !ctrl && c != '\0' && !c.is_control() => {
+                            search.query.push(c);
+                            true
+                        }
+                        _ => false,
+                    };
+                    if changed {
+                        search.current = None;
+                    }
+                }
+            }
+        }
+
+        if changed {
+            self.update_search(0, 0);
+            self.jump_search(true);
+        }
+        self.damage.add_all();
+        self.redraw();
+    }
+
+    /// The first line before the given one or after it whose row has the mark
+    fn find_mark(&self, line: usize, back: bool, mark: u8) -> Option<usize> {
+        let marked = |&line: &usize| self.line(line)[0].mark & mark != 0;
+        if back {
+            (0..line).rev().find(marked)
+        } else {
+            (line + 1..self.scrollback.len() + self.ransid.state.h).find(marked)
+        }
+    }
+
+    /// Scroll the previous or the next prompt to the top of the view
+    fn jump_prompt(&mut self, back: bool) {
+        if let Some(line) = self.find_mark(self.top_line(), back, MARK_PROMPT) {
+            self.scroll_view(self.scrollback.len().saturating_sub(line));
+        }
+    }
+
+    /// Select the lines of output of the last command that finished in copy
+    /// mode, ready to be copied with y
+    fn select_last_output(&mut self) {
+        let state = &self.ransid.state;
+        if state.w == 0 || state.h == 0 {
+            return;
+        }
+        let cursor_line = self.scrollback.len() + cmp::min(state.y, state.h - 1);
+        // The prompt the cursor is at comes after the output
+        let prompt = match self.find_mark(cursor_line + 1, true, MARK_PROMPT) {
+            Some(prompt) => prompt,
+            None => return,
+        };
+        // The output has to start after the prompt of its own command, as a
+        // command that printed nothing has no output marked
+        let command = self.find_mark(prompt, true, MARK_PROMPT);
+        let start = match self.find_mark(prompt, true, MARK_OUTPUT) {
+            Some(start) if command.is_none_or(|command| start > command) => start,
+            _ => return,
+        };
+
+        let mut vi = ViMode::new(0, start);
+        vi.toggle_selection(ViSelection::Line);
+        vi.line = prompt - 1;
+        self.vi = Some(vi);
+        self.scroll_to_line(prompt - 1);
+        self.scroll_to_line(start);
+        self.damage.add_all();
+        self.redraw();
+    }
+
+    /// Start copy mode with its cursor where the cursor of the terminal is
+    fn start_vi(&mut self) {
+        let state = &self.ransid.state;
+        // The window may be too small for a single block
+        if state.w == 0 || state.h == 0 {
+            return;
+        }
+        let x = cmp::min(state.x, state.w - 1);
+        let line = self.scrollback.len() + cmp::min(state.y, state.h - 1);
+        self.vi = Some(ViMode::new(x, line));
+        self.scroll_to_line(line);
+        self.damage.add_all();
+        self.redraw();
+    }
+
+    /// Leave copy mode and go back to the bottom of the scrollback
+    fn stop_vi(&mut self) {
+        self.vi = None;
+        if self.search.as_ref().is_some_and(|search| !search.typing) {
+            self.search = None;
+        }
+        self.scroll_view(0);
+        self.damage.add_all();
+        self.redraw();
+    }
+
+    /// hjkl, w, b, e, 0, $, gg and G move the cursor of copy mode, / searches and
+    /// n and N move between the matches. v, V and Ctrl-v select characters,
+    /// lines or a block, which y copies. Escape stops selecting, and leaves
+    /// copy mode when nothing is selected, the same as q
+    fn vi_key(&mut self, scancode: u8, character: char) {
+        let (x, line, pending_g, selecting) = match self.vi {
+            Some(ref mut vi) => {
+                let pending_g = vi.pending_g;
+                vi.pending_g = false;
+                (vi.x, vi.line, pending_g, vi.selection.is_some())
+            }
+            None => return,
+        };
+        let w = self.ransid.state.w;
+        let last_line = (self.scrollback.len() + self.ransid.state.h).saturating_sub(1);
+
+        let moved = match character {
+            _ if scancode == orbclient::K_ESC && selecting => {
+                if let Some(ref mut vi) = self.vi {
+                    vi.selection = None;
+                }
+                None
+            }
+            _ if scancode == orbclient::K_ESC => return self.stop_vi(),
+            'q' => return self.stop_vi(),
+            'v' | 'V' if self.ctrl => {
+                if let Some(ref mut vi) = self.vi {
+                    vi.toggle_selection(ViSelection::Block);
+                }
+                None
+            }
+            'v' | 'V' => {
+                let kind = if character == 'v' {
+                    ViSelection::Char
+                } else {
+                    ViSelection::Line
+                };
+                if let Some(ref mut vi) = self.vi {
+                    vi.toggle_selection(kind);
+                }
+                None
+            }
+            'y' if selecting => {
+                let text = self.vi_text();
+                self.window.set_clipboard(&text);
+                return self.stop_vi();
+            }
+            'h' => Some((x.saturating_sub(1), line)),
+            'l' => Some((cmp::min(x + 1, w.saturating_sub(1)), line)),
+            'j' => Some((x, cmp::min(line + 1, last_line))),
+            'k' => Some((x, line.saturating_sub(1))),
+            '0' => Some((0, line)),
+            '$' => {
+                let end = self
+                    .line(line)
+                    .iter()
+                    .rposition(|block| block.width != 0 && block.c != '\0' && block.c != ' ');
+                Some((end.unwrap_or(0), line))
+            }
+            'w' | 'b' | 'e' => Some(self.vi_word(character, x, line)),
+            'g' if pending_g => Some((0, 0)),
+            'g' => {
+                if let Some(ref mut vi) = self.vi {
+                    vi.pending_g = true;
+                }
+                None
+            }
+            'G' => Some((0, last_line)),
+            '/' => return self.start_search(),
+            'n' => return self.vi_jump(false),
+            'N' => return self.vi_jump(true),
+            _ => None,
+        };
+
+        if let Some((x, line)) = moved {
+            if let Some(ref mut vi) = self.vi {
+                vi.x = x;
+                vi.line = line;
+            }
+            self.scroll_to_line(line);
+        }
+        self.damage.add_all();
+        self.redraw();
+    }
+
+    /// Move the cursor of copy mode to the search match after it, or before it
+    fn vi_jump(&mut self, back: bool) {
+        let position = match self.vi {
+            Some(ref vi) => (vi.line, vi.x),
+            None => return,
+        };
+        let found = match self.search {
+            Some(ref mut search) if !search.matches.is_empty() => {
+                let matches = &search.matches;
+                let index = if back {
+                    matches
+                        .iter()
+                        .rposition(|found| (found.line, found.start) < position)
+                        .unwrap_or(matches.len() - 1)
+                } else {
+                    matches
+                        .iter()
+                        .position(|found| (found.line, found.start) > position)
+                        .unwrap_or(0)
+                };
+                search.current = Some(index);
+                matches[index]
+            }
+            _ => return,
+        };
+
+        if let Some(ref mut vi) = self.vi {
+            vi.x = found.start;
+            vi.line = found.line;
+        }
+        self.scroll_to_line(found.line);
+        self.damage.add_all();
+        self.redraw();
+    }
+
+    /// Whether the block is blank, part of a word or punctuation, which words
+    /// are split between
+    fn vi_class(&self, x: usize, line: usize) -> u8 {
+        let blocks = self.line(line);
+        let x = match blocks.get(x) {
+            Some(block) if block.width == 0 && x > 0 => x - 1,
+            Some(_) => x,
+            None => return 0,
+        };
+        match blocks[x].c {
+            c if c == '\0' || c.is_whitespace() => 0,
+            c if c.is_alphanumeric() || c == '_' => 1,
+            _ => 2,
+        }
+    }
+
+    /// The block after the given one, or before it, going on to the next or the
+    /// previous line at either end of a line. Spacers are stepped over
+    fn vi_step(&self, (x, line): (usize, usize), forward: bool) -> Option<(usize, usize)> {
+        let w = self.ransid.state.w;
+        let last_line = (self.scrollback.len() + self.ransid.state.h).saturating_sub(1);
+        let (x, line) = if forward {
+            if x + 1 < w {
+                (x + 1, line)
+            } else if line < last_line {
+                (0, line + 1)
+            } else {
+                return None;
+            }
+        } else if x > 0 {
+            (x - 1, line)
+        } else if line > 0 {
+            (w - 1, line - 1)
+        } else {
+            return None;
+        };
+
+        match self.line(line).get(x) {
+            Some(block) if block.width == 0 => self.vi_step((x, line), forward),
+            _ => Some((x, line)),
+        }
+    }
+
+    /// Where w moves to the start of the next word, e to the end of the word or
+    /// the next one, and b to the start of the word or the previous one
+    fn vi_word(&self, motion: char, x: usize, line: usize) -> (usize, usize) {
+        let class = |(x, line): (usize, usize)| self.vi_class(x, line);
+        let mut position = (x, line);
+        if motion == 'w' {
+            // The rest of the word, which ends at the end of its line
+            let start = class(position);
+            while let Some(next) = self.vi_step(position, true) {
+                let same = next.1 == position.1 && class(next) == start;
+                position = next;
+                if !same {
+                    break;
+                }
+            }
+            while class(position) == 0 {
+                match self.vi_step(position, true) {
+                    Some(next) => position = next,
+                    None => break,
+                }
+            }
+        } else {
+            let forward = motion == 'e';
+            // Blanks up to the word, then the word up to where it ends
+            match self.vi_step(position, forward) {
+                Some(next) => position = next,
+                None => return position,
+            }
+            while class(position) == 0 {
+                match self.vi_step(position, forward) {
+                    Some(next) => position = next,
+                    None => return position,
+                }
+            }
+            while let Some(next) = self.vi_step(position, forward) {
+                if next.1 != position.1 || class(next) != class(position) {
+                    break;
+                }
+                position = next;
+            }
+        }
+        position
+    }
+
+    /// The text selected in copy mode, with the blanks at the end of every line
+    /// left out
+    fn vi_text(&self) -> String {
+        let vi = match self.vi {
+            Some(ref vi) => vi,
+            None => return String::new(),
+        };
+        let w = self.ransid.state.w;
+
+        let mut lines = Vec::new();
+        for line in 0..self.scrollback.len() + self.ransid.state.h {
+            let (start, end) = match vi.selected(line, w) {
+                Some(range) => range,
+                None => continue,
+            };
+
+            let blocks = self.line(line);
+            let mut text = String::new();
+            for block in blocks[cmp::min(start, blocks.len())..cmp::min(end, blocks.len())].iter() {
+                if block.width == 0 {
+                    continue;
+                }
+                match self.clusters.get(block.cluster) {
+                    Some(cluster) => text.push_str(cluster),
+                    None if block.c == '\0' => text.push(' '),
+                    None => text.push(block.c),
+                }
+            }
+            lines.push(text.trim_end().to_string());
+        }
+        lines.join("\n")
+    }
+
+    /// Label the matches of the hint patterns on the grid, unless there are none
+    fn start_hints(&mut self) {
+        let mut matches: Vec<Match> = Vec::new();
+        for y in 0..self.ransid.state.h {
+            let (text, columns) = self.row_text(y);
+            let mut row: Vec<Match> = Vec::new();
+            // Earlier patterns win where matches overlap
+            for pattern in self.hint_patterns.iter() {
+                for found in pattern.find_all(&text, &columns, y) {
+                    if found.start < found.end
+                        && row
+                            .iter()
+                            .all(|other| found.end <= other.start || other.end <= found.start)
+                    {
+                        row.push(found);
+                    }
+                }
+            }
+            row.sort_by_key(|found| found.start);
+            matches.extend(row);
+        }
+
+        if !matches.is_empty() {
+            self.hints = Some(Hints::new(matches));
+            self.damage_hints();
+            self.redraw();
+        }
+    }
+
+    /// Typing a label copies or opens its match, Backspace takes back the last
+    /// letter and Escape leaves hints mode
+    fn hint_key(&mut self, scancode: u8, character: char) {
+        let mut done = scancode == orbclient::K_ESC;
+        if let Some(ref mut hints) = self.hints {
+            if scancode == orbclient::K_BKSP {
+                hints.typed.pop();
+            } else if let Some(hint) = hints.push(character.to_ascii_lowercase()) {
+                match hint.found.action {
+                    HintAction::Copy => self.window.set_clipboard(&hint.found.text),
+                    HintAction::Open => open::spawn(&hint.found.args, self.cwd.as_deref()),
+                }
+                done = true;
+            }
+        }
+
+        self.damage_hints();
+        if done {
+            self.hints = None;
+        }
+        self.redraw();
+    }
+
+    fn damage_hints(&mut self) {
+        if let Some(ref hints) = self.hints {
+            for hint in hints.hints.iter() {
+                let found = &hint.found;
+                self.damage
+                    .add(found.start, found.y, found.end - found.start, 1);
+            }
+        }
+    }
+
+    /// Damage every block that is part of the given link
+    fn damage_link(&mut self, link: u32) {
+        if link == 0 {
+            return;
+        }
+
+        self.damage_blocks(|block| block.link == link);
+    }
+
+    /// Damage every block with blinking text, which has to be drawn again
+    /// whenever it is shown or hidden
+    fn damage_blinking(&mut self) {
+        self.damage_blocks(|block| block.style.blink);
+    }
+
+    /// Damage every block in the view that the predicate is true for
+    fn damage_blocks<F: Fn(&Block) -> bool>(&mut self, predicate: F) {
+        let top = self.top_line();
+        let blocks: Vec<(usize, usize)> = (0..self.ransid.state.h)
+            .flat_map(|y| {
+                self.line(top + y)
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, block)| predicate(block))
+                    .map(move |(x, _)| (x, y))
+            })
+            .collect();
+        for (x, y) in blocks {
+            self.damage.add(x, y, 1, 1);
+        }
+    }
+
+    fn cursor_position(&self) -> Option<(usize, usize)> {
+        let state = &self.ransid.state;
+        if state.cursor && state.x < state.w && state.y < state.h {
+            Some((state.x, state.y))
+        } else {
+            None
+        }
+    }
+
+    fn resize_grid(&mut self, w: usize, h: usize) {
+        if w != self.ransid.state.w || h != self.ransid.state.h {
+            // Matches move around when the grid changes size
+            self.hints = None;
+            self.hovered_match = None;
+
+            let mut grid = vec![
+                Block {
+                    c: '\0',
+                    cluster: 0,
+                    fg: CellColor::Default,
+                    bg: CellColor::Default,
+                    style: Style::default(),
+                    underline_color: None,
+                    link: 0,
+                    mark: 0,
+                    width: 1
+                };
+                w * h
+            ]
+            .into_boxed_slice();
+
+            let mut alt_grid = grid.clone();
+
+            for y in 0..cmp::min(h, self.ransid.state.h) {
+                for x in 0..cmp::min(w, self.ransid.state.w) {
+                    grid[y * w + x] = self.grid[y * self.ransid.state.w + x];
+                    alt_grid[y * w + x] = self.alt_grid[y * self.ransid.state.w + x];
+                }
+            }
+
+            self.ransid.resize(w, h);
+            self.grid = grid;
+            self.alt_grid = alt_grid;
+            self.damage.resize(w, h);
+            self.cursor = None;
+
+            // Wide characters in the last column may have lost their spacer
+            if w > 0 {
+                for y in 0..h {
+                    repair_wide(&mut self.grid, w, w - 1, y, &mut self.damage);
+                    repair_wide(&mut self.alt_grid, w, w - 1, y, &mut self.damage);
+                }
+            }
+
+            // Rows of the grid were cut off or gained blank columns
+            let scrollback_len = self.scrollback.len();
+            self.update_search(scrollback_len, 0);
+
+            //TODO: Figure out what should happen on resize
+            self.selection = None;
+            self.last_selection = None;
+
+            if self.title_template.is_some() {
+                self.update_title();
+            }
+        }
+    }
+
+    pub fn selection_text(&self) -> String {
+        let mut string = String::new();
+        if let Some(selection) = self.selection {
+            let mut start = cmp::min(selection.0, selection.1);
+            // Selecting the second half of a wide character selects all of it
+            if start > 0
+                && self
+                    .selected_block(start)
+                    .is_some_and(|block| block.width == 0)
+            {
+                start -= 1;
+            }
+
+            let mut skipping = false;
+            for i in start..cmp::max(selection.0, selection.1) {
+                if let Some(block) = self.selected_block(i) {
+                    if block.width == 0 {
+                        continue;
+                    } else if block.c == '\0' {
+                        skipping = true;
+                    } else {
+                        if skipping {
+                            string.push('\n');
+                            skipping = false;
+                        }
+                        match self.clusters.get(block.cluster) {
+                            Some(text) => string.push_str(text),
+                            None => string.push(block.c),
+                        }
+                    }
+                }
+            }
+        }
+        string
+    }
+
+    fn is_selected(&self, i: usize) -> bool {
+        match self.selection {
+            Some(selection) => {
+                i >= cmp::min(selection.0, selection.1) && i < cmp::max(selection.0, selection.1)
+            }
+            None => false,
+        }
+    }
+
+    fn damage_selection(&mut self, selection: (usize, usize)) {
+        let w = self.ransid.state.w;
+        let top = self.top_line();
+        // Only the part of the selection in the view is damaged
+        let end = cmp::min(
+            cmp::max(selection.0, selection.1),
+            (top + self.ransid.state.h) * w,
+        );
+        let mut i = cmp::max(cmp::min(selection.0, selection.1), top * w);
+        while i < end {
+            let (x, y) = (i % w, i / w - top);
+            let len = cmp::min(w - x, end - i);
+            self.damage.add(x, y, len, 1);
+            i += len;
+        }
+    }
+
+    /// Redraw, unless the last frame was drawn too recently. Anything written in
+    /// the meantime stays damaged and is coalesced into the next frame
+    pub fn sync(&mut self) {
+        if self.last_frame.elapsed() >= FRAME_DURATION {
+            self.redraw();
+        }
+    }
+
+    /// Whether the screen changes with time alone, which it does while the
+    /// cursor blinks in the focused window, while blinking text is in view, or
+    /// while damage is held back for the next frame
+    pub fn animating(&self) -> bool {
+        if (self.cursor_blink && self.focused) || !self.damage.is_empty() {
+            return true;
+        }
+        let top = self.top_line();
+        (0..self.ransid.state.h).any(|y| self.line(top + y).iter().any(|block| block.style.blink))
+    }
+
+    /// Apply a change to the zoom, either by resizing the window around the
+    /// grid or by fitting the grid into the window
+    fn zoomed(&mut self) {
+        if self.zoom_resizes_window {
+            let (width, height) = self
+                .block_handler
+                .window_size(self.ransid.state.w, self.ransid.state.h);
+            self.window.set_size(width, height);
+        }
+
+        self.update_block_size();
+    }
+
+    pub fn update_block_size(&mut self) {
+        self.glyph_cache.set_metrics(self.block_handler.metrics);
+
+        let (w, h) = self
+            .block_handler
+            .how_many_blocks_fit(self.window.width() as usize, self.window.height() as usize);
+
+        self.resize_grid(w, h);
+        self.redraw_all();
+    }
+
+    fn change_opacity(&mut self, change: f32) {
+        let opacity = self.alpha as f32 / 255.0 + change;
+        self.alpha = (opacity.clamp(0.0, 1.0) * 255.0).round() as u8;
+        self.redraw_all();
+    }
+
+    fn redraw_all(&mut self) {
+        // Every block has to be drawn again, and whatever is left around the grid
+        // has to be cleared to the default background
+        let background = self.color(CellColor::Default, true);
+        let background = Color {
+            data: ((self.alpha as u32) << 24) | (background.data & 0xFFFFFF),
+        };
+        self.window.set(background);
+        self.damage.add_all();
+        self.redraw();
+    }
+
+    /// The colour a block given `color` is drawn in, opaque, as the opacity is
+    /// applied when blocks are drawn
+    fn color(&self, color: CellColor, background: bool) -> Color {
+        let color = self
+            .parser
+            .cell_color(&self.ransid.state, color, background);
+        Color {
+            data: 0xFF000000 | (color.as_rgb() & 0xFFFFFF),
+        }
+    }
+
+    /// Draw every block again after programs changed colours, as blocks are
+    /// drawn in the colours as they are now
+    fn recolor(&mut self) {
+        // The window around the grid is cleared to the default background
+        let background = self.color(CellColor::Default, true);
+        self.window.set(Color {
+            data: ((self.alpha as u32) << 24) | (background.data & 0xFFFFFF),
+        });
+        self.damage.add_all();
+    }
+
+    /// Call the function with every block of the grids and the scrollback
+    fn for_each_block<F: FnMut(&mut Block)>(&mut self, f: F) {
+        self.grid
+            .iter_mut()
+            .chain(self.alt_grid.iter_mut())
+            .chain(self.scrollback.iter_mut().flat_map(|row| row.iter_mut()))
+            .for_each(f);
+    }
+
+    /// Drop the grapheme clusters and link URIs that no block refers to any more,
+    /// once there are too many of them to keep around
+    fn compact_clusters(&mut self) {
+        if self.clusters.is_full() {
+            let mut used = vec![false; self.clusters.len() + 1];
+            self.for_each_block(|block| used[block.cluster as usize] = true);
+            let indices = self.clusters.compact(&used);
+            self.for_each_block(|block| block.cluster = indices[block.cluster as usize]);
+            // Glyphs of clusters are cached by their old indices
+            self.glyph_cache.clear_clusters();
+        }
+
+        if self.links.is_full() {
+            let mut used = vec![false; self.links.len() + 1];
+            self.for_each_block(|block| used[block.link as usize] = true);
+            // Characters printed from now on still link to the current URI
+            used[self.link as usize] = true;
+            let indices = self.links.compact(&used);
+            self.for_each_block(|block| block.link = indices[block.link as usize]);
+            self.link = indices[self.link as usize];
+            self.hovered_link = indices[self.hovered_link as usize];
+        }
+    }
+
+    /// Set the title of the window from the title template, or to the title
+    /// programs set when there is no template
+    fn update_title(&mut self) {
+        let title = match self.title_template {
+            Some(ref template) => {
+                let cwd = self.cwd.as_ref().map(|cwd| short_path(cwd));
+                expand_title(template, |name| match name {
+                    "title" => Some(self.title.clone()),
+                    "cwd" => Some(cwd.clone().unwrap_or_default()),
+                    "cols" => Some(self.ransid.state.w.to_string()),
+                    "rows" => Some(self.ransid.state.h.to_string()),
+                    _ => None,
+                })
+            }
+            None => self.title.clone(),
+        };
+        self.window.set_title(&title);
+    }
+
+    pub fn write(&mut self, buf: &[u8], sync: bool) -> Result<usize> {
+        let scrollback_len = self.scrollback.len();
+        // The oldest rows of the scrollback that were dropped to make room
+        let mut dropped = 0;
+        // Whether rows of the grid moved, taking the matches on them along
+        let mut rows_moved = false;
 
         {
-            let font = &self.font;
-            let font_bold = &self.font_bold;
-            let console_bg = self.ransid.state.background;
+            let console_bg = self.parser.background;
             let console_w = self.ransid.state.w;
             let console_h = self.ransid.state.h;
-            let block_width = self.block_handler.block_width;
-            let block_height = self.block_handler.block_height;
+            let block_handler = &self.block_handler;
             let alt = &mut self.alternate;
             let grid = &mut self.grid;
             let alt_grid = &mut self.alt_grid;
             let window = &mut self.window;
             let input = &mut self.input;
-            let changed = &mut self.changed;
-            let mut str_buf = [0; 4];
-            let block_handler = &self.block_handler;
-
-            self.ransid.write(buf, |event| {
-                match event {
-                    ransid::Event::Char {
+            let damage = &mut self.damage;
+            let clusters = &mut self.clusters;
+            let cursor = self.cursor;
+            let cursor_shape = &mut self.cursor_shape;
+            let cursor_blink = &mut self.cursor_blink;
+            let default_cursor = self.default_cursor;
+            let links = &mut self.links;
+            let current_link = &mut self.link;
+            let scrollback = &mut self.scrollback;
+            let scrollback_lines = self.scrollback_lines;
+            let scroll_offset = &mut self.scroll_offset;
+            let pending_mark = &mut self.pending_mark;
+            let current_title = &mut self.title;
+            let fixed_title = self.fixed_title;
+            let title_stack = &mut self.title_stack;
+            let current_cwd = &mut self.cwd;
+            let mut title_changed = false;
+            let mut recolored = false;
+
+            self.parser.advance(&mut self.ransid.state, buf, |event| {
+                let event = match event {
+                    parser::Event::Char {
                         x,
                         y,
                         c,
                         color,
-                        bold,
-                        ..
+                        style,
+                        underline_color,
+                        wide,
                     } => {
-                        let (x, y) = block_handler.get_pixels_from_block(x, y);
-
-                        if bold {
-                            font_bold
-                                .render(&c.encode_utf8(&mut str_buf), block_height as f32)
-                                .draw(window, x as i32, y as i32, cvt(color));
-                        } else {
-                            font.render(&c.encode_utf8(&mut str_buf), block_height as f32)
-                                .draw(window, x as i32, y as i32, cvt(color));
+                        let link = *current_link;
+                        // Prompts are often cleared and drawn again after they
+                        // are marked, so their marks are applied once more
+                        if *pending_mark != 0 {
+                            if let Some(ref mut block) = grid.get_mut(y * console_w) {
+                                block.mark |= *pending_mark;
+                            }
+                            *pending_mark = 0;
                         }
-
                         if let Some(ref mut block) = grid.get_mut(y * console_w + x) {
                             block.c = c;
-                            block.fg = cvt(color);
-                            block.bold = bold;
+                            block.cluster = 0;
+                            block.fg = color;
+                            block.style = style;
+                            block.underline_color = underline_color;
+                            block.link = link;
+                            block.width = if wide { 2 } else { 1 };
                         }
 
-                        changed.insert(y);
+                        damage.add(x, y, 1, 1);
+                        return;
                     }
-                    ransid::Event::Input { data } => {
-                        input.extend(data);
+                    parser::Event::Spacer { x, y } => {
+                        let i = y * console_w + x;
+                        if x > 0 && i < grid.len() {
+                            grid[i] = Block {
+                                c: '\0',
+                                cluster: 0,
+                                width: 0,
+                                ..grid[i - 1]
+                            };
+                            repair_wide(grid, console_w, x + 1, y, damage);
+                        }
+
+                        damage.add(x, y, 1, 1);
+                        return;
                     }
-                    ransid::Event::Rect { x, y, w, h, color } => {
-                        window.mode().set(Mode::Overwrite);
-                        window.rect(
-                            x as i32 * block_width as i32,
-                            y as i32 * block_height as i32,
-                            w as u32 * block_width as u32,
-                            h as u32 * block_height as u32,
-                            cvt(color),
-                        );
-                        window.mode().set(Mode::Blend);
+                    parser::Event::Combine { x, y, c } => {
+                        let mut i = y * console_w + x;
+                        if x > 0 && grid.get(i).is_some_and(|block| block.width == 0) {
+                            i -= 1;
+                        }
+
+                        if let Some(ref mut block) = grid.get_mut(i) {
+                            if block.c != '\0' {
+                                let mut text = match clusters.get(block.cluster) {
+                                    Some(text) => text.to_string(),
+                                    None => block.c.to_string(),
+                                };
+                                text.push(c);
+                                block.cluster = clusters.intern(text);
 
+                                damage.add(i % console_w, y, block.width as usize, 1);
+                            }
+                        }
+                        return;
+                    }
+                    parser::Event::CursorShape { shape, blink } => {
+                        let (shape, blink) = match shape {
+                            Some(shape) => (shape, blink),
+                            None => default_cursor,
+                        };
+                        *cursor_shape = shape;
+                        *cursor_blink = blink;
+                        if let Some((x, y)) = cursor {
+                            damage.add(x, y, 1, 1);
+                        }
+                        return;
+                    }
+                    parser::Event::Link { uri } => {
+                        *current_link = uri.map_or(0, |uri| links.intern(uri));
+                        return;
+                    }
+                    parser::Event::TitleStack { push } => {
+                        if push {
+                            if title_stack.len() == TITLE_STACK_SIZE {
+                                title_stack.remove(0);
+                            }
+                            title_stack.push(current_title.clone());
+                        } else if let Some(title) = title_stack.pop() {
+                            *current_title = title;
+                            title_changed = true;
+                        }
+                        return;
+                    }
+                    parser::Event::SizeQuery { block } => {
+                        let (block_width, block_height) = block_handler.get();
+                        let report = if block {
+                            format!("\x1B[6;{};{}t", block_height, block_width)
+                        } else {
+                            format!(
+                                "\x1B[4;{};{}t",
+                                console_h * block_height,
+                                console_w * block_width
+                            )
+                        };
+                        input.extend(report.as_bytes());
+                        return;
+                    }
+                    parser::Event::AlternateScreenQuery { mode } => {
+                        let report = format!("\x1B[?{};{}$y", mode, if *alt { 1 } else { 2 });
+                        input.extend(report.as_bytes());
+                        return;
+                    }
+                    parser::Event::Recolor { slot } => {
+                        match slot {
+                            ColorSlot::Cursor => damage.add_all(),
+                            _ => recolored = true,
+                        }
+                        return;
+                    }
+                    parser::Event::Cwd { path } => {
+                        *current_cwd = Some(path);
+                        title_changed = true;
+                        return;
+                    }
+                    parser::Event::Mark { y, mark } => {
+                        let flag = match mark {
+                            PromptMark::Prompt => MARK_PROMPT,
+                            PromptMark::Command => MARK_COMMAND,
+                            PromptMark::Output => MARK_OUTPUT,
+                            PromptMark::Finished(status) => {
+                                // Failures are marked on the row of the prompt the
+                                // command was typed at
+                                // The window may be too small for a single block
+                                let empty = console_w == 0 || console_h == 0;
+                                if !empty && status.is_some_and(|status| status != 0) {
+                                    let prompt = (0..=cmp::min(y, console_h - 1))
+                                        .rev()
+                                        .find(|&row| grid[row * console_w].mark & MARK_PROMPT != 0);
+                                    match prompt {
+                                        Some(row) => {
+                                            grid[row * console_w].mark |= MARK_FAILED;
+                                            damage.add(0, row, 1, 1);
+                                        }
+                                        None => {
+                                            let row = scrollback
+                                                .iter_mut()
+                                                .rev()
+                                                .find(|row| row[0].mark & MARK_PROMPT != 0);
+                                            if let Some(row) = row {
+                                                row[0].mark |= MARK_FAILED;
+                                            }
+                                        }
+                                    }
+                                }
+                                return;
+                            }
+                        };
+                        // After a line feed on the last row the cursor is below
+                        // the grid until the next character scrolls it, so the
+                        // output mark waits for that character
+                        let applied = match grid.get_mut(y * console_w) {
+                            Some(block) => {
+                                block.mark |= flag;
+                                true
+                            }
+                            None => false,
+                        };
+                        match mark {
+                            PromptMark::Prompt => *pending_mark = MARK_PROMPT,
+                            PromptMark::Output if applied => (),
+                            _ => *pending_mark |= flag,
+                        }
+                        return;
+                    }
+                    parser::Event::Rect { x, y, w, h, color } => {
                         for y2 in y..y + h {
                             for x2 in x..x + w {
                                 if let Some(ref mut block) = grid.get_mut(y2 * console_w + x2) {
                                     block.c = '\0';
-                                    block.bg = cvt(color);
+                                    block.cluster = 0;
+                                    block.bg = color;
+                                    block.style = Style::default();
+                                    block.underline_color = None;
+                                    block.link = 0;
+                                    block.width = 1;
+                                }
+                            }
+                            // Marks stay on rows until the whole row is erased, as
+                            // a block is cleared before every character printed
+                            if x == 0 && w >= console_w {
+                                if let Some(ref mut block) = grid.get_mut(y2 * console_w) {
+                                    block.mark = 0;
                                 }
                             }
-                            changed.insert(y2);
+
+                            if x > 0 {
+                                repair_wide(grid, console_w, x - 1, y2, damage);
+                            }
+                            repair_wide(grid, console_w, x + w, y2, damage);
                         }
+
+                        damage.add(x, y, w, h);
+                        return;
+                    }
+                    parser::Event::Ransid(event) => event,
+                };
+
+                match event {
+                    ransid::Event::Char { .. } | ransid::Event::Rect { .. } => (),
+                    ransid::Event::Input { data } => {
+                        input.extend(data);
                     }
                     ransid::Event::ScreenBuffer { alternate, clear } => {
                         if *alt != alternate {
                             mem::swap(grid, alt_grid);
+                            rows_moved = true;
+                            // The scrollback belongs to the primary screen
+                            *scroll_offset = 0;
 
-                            window.set(cvt(console_bg));
-
-                            for y in 0..console_h {
-                                for x in 0..console_w {
-                                    let block = &mut grid[y * console_w + x];
-
-                                    if clear {
-                                        block.c = '\0';
-                                        block.bg = cvt(console_bg);
-                                    }
-
-                                    window.mode().set(Mode::Overwrite);
-                                    window.rect(
-                                        x as i32 * block_width as i32,
-                                        y as i32 * block_height as i32,
-                                        block_width as u32,
-                                        block_height as u32,
-                                        block.bg,
-                                    );
-                                    window.mode().set(Mode::Blend);
-
-                                    if block.c != '\0' {
-                                        if block.bold {
-                                            font_bold
-                                                .render(
-                                                    &block.c.encode_utf8(&mut str_buf),
-                                                    block_height as f32,
-                                                )
-                                                .draw(
-                                                    window,
-                                                    x as i32 * block_width as i32,
-                                                    y as i32 * block_height as i32,
-                                                    block.fg,
-                                                );
-                                        } else {
-                                            font.render(
-                                                &block.c.encode_utf8(&mut str_buf),
-                                                block_height as f32,
-                                            )
-                                            .draw(
-                                                window,
-                                                x as i32 * block_width as i32,
-                                                y as i32 * block_height as i32,
-                                                block.fg,
-                                            );
-                                        }
-                                    }
+                            if clear {
+                                for block in grid.iter_mut() {
+                                    block.c = '\0';
+                                    block.cluster = 0;
+                                    block.bg = console_bg;
+                                    block.style = Style::default();
+                                    block.underline_color = None;
+                                    block.link = 0;
+                                    block.mark = 0;
+                                    block.width = 1;
                                 }
-                                changed.insert(y as usize);
                             }
+
+                            damage.add(0, 0, console_w, console_h);
                         }
                         *alt = alternate;
                     }
@@ -705,102 +2408,412 @@ impl Console {
                         w,
                         h,
                     } => {
-                        let width = window.width() as usize;
-                        let pixels = window.data_mut();
+                        // Rows scrolled off the top of the screen are kept in the
+                        // scrollback, with the view staying where it is when it
+                        // has been scrolled back. Scrolling a region that does not
+                        // reach the bottom, such as above a status line, leaves the
+                        // rows it drops out of the scrollback
+                        let scrolled = to_y == 0
+                            && from_y > 0
+                            && to_x == 0
+                            && w == console_w
+                            && from_y + h == console_h;
+                        if scrolled && !*alt && scrollback_lines > 0 {
+                            for y in 0..cmp::min(from_y, console_h) {
+                                let row = &grid[y * console_w..(y + 1) * console_w];
+                                // Once the scrollback is full, its oldest row is reused
+                                let oldest = if scrollback.len() >= scrollback_lines {
+                                    dropped += 1;
+                                    scrollback.pop_front()
+                                } else {
+                                    None
+                                };
+                                scrollback.push_back(match oldest {
+                                    Some(mut oldest) if oldest.len() == console_w => {
+                                        oldest.copy_from_slice(row);
+                                        oldest
+                                    }
+                                    _ => row.to_vec().into_boxed_slice(),
+                                });
+                                if *scroll_offset > 0 {
+                                    *scroll_offset = cmp::min(*scroll_offset + 1, scrollback.len());
+                                }
+                            }
+                        }
 
                         for raw_y in 0..h {
                             let y = if from_y > to_y { raw_y } else { h - raw_y - 1 };
 
-                            for pixel_y in 0..block_height {
-                                {
-                                    let off_from = ((from_y + y) * block_height + pixel_y) * width
-                                        + from_x * block_width;
-                                    let off_to = ((to_y + y) * block_height + pixel_y) * width
-                                        + to_x * block_width;
-                                    let len = w * block_width;
-
-                                    if off_from + len <= pixels.len()
-                                        && off_to + len <= pixels.len()
-                                    {
-                                        unsafe {
-                                            let data_ptr = pixels.as_mut_ptr() as *mut u32;
-                                            ptr::copy(
-                                                data_ptr.offset(off_from as isize),
-                                                data_ptr.offset(off_to as isize),
-                                                len,
-                                            );
-                                        }
-                                    }
+                            let off_from = (from_y + y) * console_w + from_x;
+                            let off_to = (to_y + y) * console_w + to_x;
+                            let len = w;
+
+                            if off_from + len <= grid.len() && off_to + len <= grid.len() {
+                                unsafe {
+                                    let data_ptr = grid.as_mut_ptr();
+                                    ptr::copy(data_ptr.add(off_from), data_ptr.add(off_to), len);
                                 }
                             }
 
-                            {
-                                let off_from = (from_y + y) * console_w + from_x;
-                                let off_to = (to_y + y) * console_w + to_x;
-                                let len = w;
-
-                                if off_from + len <= grid.len() && off_to + len <= grid.len() {
-                                    unsafe {
-                                        let data_ptr = grid.as_mut_ptr();
-                                        ptr::copy(
-                                            data_ptr.offset(off_from as isize),
-                                            data_ptr.offset(off_to as isize),
-                                            len,
-                                        );
-                                    }
+                            // Wide characters may have been split at either edge
+                            for &edge_x in &[to_x, to_x + w] {
+                                if edge_x > 0 {
+                                    repair_wide(grid, console_w, edge_x - 1, to_y + y, damage);
                                 }
+                                repair_wide(grid, console_w, edge_x, to_y + y, damage);
                             }
-
-                            changed.insert(to_y + y);
                         }
+
+                        damage.add(to_x, to_y, w, h);
+                        rows_moved = true;
                     }
                     ransid::Event::Resize { w, h } => {
                         //TODO: Make sure grid is resized
-                        window.set_size(
-                            w as u32 * block_width as u32,
-                            h as u32 * block_height as u32,
-                        );
+                        let (width, height) = block_handler.window_size(w, h);
+                        window.set_size(width, height);
                     }
                     ransid::Event::Title { title } => {
-                        window.set_title(&title);
+                        if !fixed_title {
+                            *current_title = title;
+                            title_changed = true;
+                        }
                     }
                 }
             });
+
+            if title_changed {
+                self.update_title();
+            }
+            if recolored {
+                self.recolor();
+            }
         }
 
-        if self.ransid.state.cursor
-            && self.ransid.state.x < self.ransid.state.w
-            && self.ransid.state.y < self.ransid.state.h
-        {
-            let (x, y) = self
-                .block_handler
-                .get_pixels_from_block(self.ransid.state.x, self.ransid.state.y);
+        self.compact_clusters();
 
-            let (block_width, block_height) = self.block_handler.get();
+        // Hints label where matches were, which is not where they are once
+        // rows moved
+        if rows_moved && self.hints.is_some() {
+            self.damage_hints();
+            self.hints = None;
+        }
 
-            self.invert(x, y, block_width, block_height);
-            self.changed.insert(y as usize);
+        // The selections and the copy mode cursor stay on the rows they were
+        // on, which moved up
+        if dropped > 0 {
+            if let Some(ref mut vi) = self.vi {
+                vi.line = vi.line.saturating_sub(dropped);
+                if let Some((_, _, ref mut line)) = vi.selection {
+                    *line = line.saturating_sub(dropped);
+                }
+            }
+            let dropped = dropped * self.ransid.state.w;
+            let moved = |(start, end): (usize, usize)| {
+                (start.saturating_sub(dropped), end.saturating_sub(dropped))
+            };
+            self.selection = self.selection.map(moved);
+            self.last_selection = self.last_selection.map(moved);
+        }
+        // Rows that were in the scrollback before did not change, so only the
+        // rows that were added to it and the grid are searched again
+        if self.search.is_some() {
+            self.update_search(scrollback_len.saturating_sub(dropped), dropped);
+        }
+        // Changes to the grid are damaged where they are on the grid, which is
+        // not where they are in the view once it has been scrolled back
+        if self.scroll_offset > 0 && !self.damage.is_empty() {
+            self.damage.add_all();
         }
 
-        if let Some(selection) = self.selection {
-            for i in cmp::min(selection.0, selection.1)..cmp::max(selection.0, selection.1) {
-                let (x, y) = self
-                    .block_handler
-                    .get_pixels_from_block(i % self.ransid.state.w, i / self.ransid.state.w);
+        if sync {
+            self.sync();
+        }
 
-                let (block_width, block_height) = self.block_handler.get();
+        Ok(buf.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render_target::Offscreen;
+    use crate::DEFAULT_DPI;
+
+    fn console() -> Console<Offscreen> {
+        Console::with_target(
+            &Config::default(),
+            Offscreen::new(640, 384),
+            DEFAULT_DPI,
+            1.0,
+        )
+    }
 
-                self.invert(x, y, block_width, block_height);
-                self.changed.insert(y as usize);
+    /// Run commands the way a shell with OSC 133 integration prints them
+    fn run(console: &mut Console<Offscreen>, commands: usize) {
+        let mut output = String::new();
+        for command in 0..commands {
+            output.push_str("\x1B]133;A\x07\r\x1B[K$ \x1B]133;B\x07");
+            output.push_str(&format!("cmd{}\r\n\x1B]133;C\x07", command));
+            for line in 0..3 {
+                output.push_str(&format!("out {} {}\r\n", command, line));
             }
+            output.push_str(&format!("\x1B]133;D;{}\x07", command % 2));
         }
+        output.push_str("\x1B]133;A\x07$ ");
+        console.write(output.as_bytes(), false).unwrap();
+    }
 
-        self.last_selection = self.selection;
+    #[test]
+    fn select_last_output() {
+        let mut console = console();
+        run(&mut console, 2);
+        assert_eq!(console.grid[console.ransid.state.w].mark, MARK_OUTPUT);
 
-        if sync {
-            self.sync();
+        console.select_last_output();
+        assert_eq!(console.vi_text(), "out 1 0\nout 1 1\nout 1 2");
+    }
+
+    #[test]
+    fn select_last_output_of_a_command_without_output() {
+        let mut console = console();
+        run(&mut console, 1);
+        console
+            .write(
+                b"\x1B]133;B\x07cd x\r\n\x1B]133;C\x07\x1B]133;D;0\x07",
+                false,
+            )
+            .unwrap();
+        console.write(b"\x1B]133;A\x07$ ", false).unwrap();
+
+        console.select_last_output();
+        assert!(console.vi.is_none());
+    }
+
+    #[test]
+    fn failures_are_not_marked_without_rows() {
+        let mut console =
+            Console::with_target(&Config::default(), Offscreen::new(640, 5), DEFAULT_DPI, 1.0);
+        assert_eq!(console.ransid.state.h, 0);
+        console
+            .write(b"\x1B]133;A\x07$ \x1B]133;D;1\x07", false)
+            .unwrap();
+    }
+
+    #[test]
+    fn select_last_output_after_scrolling() {
+        let mut console = console();
+        run(&mut console, 20);
+
+        console.select_last_output();
+        assert_eq!(console.vi_text(), "out 19 0\nout 19 1\nout 19 2");
+    }
+
+    #[test]
+    fn scrolling_above_a_status_line_keeps_no_scrollback() {
+        let mut console = console();
+        let rows = console.ransid.state.h;
+        console
+            .write(
+                format!("\x1B[1;{}r\x1B[{}H", rows - 1, rows - 1).as_bytes(),
+                false,
+            )
+            .unwrap();
+        for line in 0..rows * 2 {
+            console
+                .write(format!("\r\nline {}", line).as_bytes(), false)
+                .unwrap();
         }
+        assert!(console.scrollback.is_empty());
+    }
 
-        Ok(buf.len())
+    #[test]
+    fn failed_commands_are_marked() {
+        let mut console = console();
+        run(&mut console, 2);
+
+        let prompts: Vec<u8> = (0..10)
+            .map(|line| console.line(line)[0].mark)
+            .filter(|mark| mark & MARK_PROMPT != 0)
+            .collect();
+        assert_eq!(prompts.len(), 3);
+        assert_eq!(prompts[0] & MARK_FAILED, 0);
+        assert_ne!(prompts[1] & MARK_FAILED, 0);
+        assert_eq!(prompts[2] & MARK_FAILED, 0);
+    }
+
+    #[test]
+    fn palette_changes_only_recolor_their_own_blocks() {
+        let mut console = console();
+        // Palette 1 is changed to the colour palette 2 had, before palette 2 and
+        // 4 change as well
+        console
+            .write(b"\x1B[31mA\x1B[32mB\x1B[38;2;0;0;128mC\x1B[44mD", false)
+            .unwrap();
+        console
+            .write(
+                b"\x1B]4;1;#008000\x07\x1B]4;2;#0000ff\x07\x1B]4;4;#ffffff\x07",
+                false,
+            )
+            .unwrap();
+
+        let fg = |console: &Console<Offscreen>, x: usize| console.color(console.grid[x].fg, false);
+        assert_eq!(fg(&console, 0).data, 0xFF008000);
+        assert_eq!(fg(&console, 1).data, 0xFF0000FF);
+        assert_eq!(fg(&console, 2).data, 0xFF000080);
+        assert_eq!(console.color(console.grid[3].bg, true).data, 0xFFFFFFFF);
+    }
+
+    #[test]
+    fn search_follows_output() {
+        let mut console = console();
+        console.scrollback_lines = 20;
+        let print = |console: &mut Console<Offscreen>, lines: ::std::ops::Range<usize>| {
+            for line in lines {
+                let text = format!("line {}\r\n", line);
+                console.write(text.as_bytes(), false).unwrap();
+            }
+        };
+        print(&mut console, 0..40);
+        console.search = Some(Search {
+            query: "line 2".to_string(),
+            ..Search::default()
+        });
+        console.update_search(0, 0);
+        console.search.as_mut().unwrap().current = Some(1);
+        let current = console.line_text(console.search.as_ref().unwrap().matches[1].line);
+
+        // The oldest rows of the scrollback are dropped as more rows are added
+        print(&mut console, 40..45);
+        let search = console.search.take().unwrap();
+        let found = console.line_text(search.matches[search.current.unwrap()].line);
+        assert_eq!(found, current);
+
+        console.search = Some(Search {
+            query: "line 2".to_string(),
+            ..Search::default()
+        });
+        console.update_search(0, 0);
+        assert!(!search.matches.is_empty());
+        assert_eq!(search.matches, console.search.unwrap().matches);
+    }
+
+    #[test]
+    fn copy_mode_follows_output() {
+        let mut console = console();
+        console.scrollback_lines = 20;
+        for line in 0..40 {
+            let text = format!("line {}\r\n", line);
+            console.write(text.as_bytes(), false).unwrap();
+        }
+        console.start_vi();
+        console.vi_key(0, 'k');
+        console.vi_key(0, 'V');
+        console.vi_key(0, 'k');
+        let selected = console.vi_text();
+        assert_eq!(selected, "line 37\nline 38");
+
+        // The oldest rows of the scrollback are dropped as more rows are added
+        for line in 40..45 {
+            let text = format!("line {}\r\n", line);
+            console.write(text.as_bytes(), false).unwrap();
+        }
+        assert_eq!(console.vi_text(), selected);
+    }
+
+    #[test]
+    fn hints_end_when_rows_move() {
+        let mut console = console();
+        console
+            .write(b"see https://example.com/\r\n", false)
+            .unwrap();
+        console.start_hints();
+        assert!(console.hints.is_some());
+
+        // Output that leaves the rows where they are keeps the labels
+        console.write(b"more", false).unwrap();
+        assert!(console.hints.is_some());
+
+        for line in 0..console.ransid.state.h {
+            let text = format!("line {}\r\n", line);
+            console.write(text.as_bytes(), false).unwrap();
+        }
+        assert!(console.hints.is_none());
+    }
+
+    #[test]
+    fn animating_only_while_something_blinks() {
+        let mut console = console();
+        console.write(b"still", false).unwrap();
+        assert!(console.animating());
+        console.redraw();
+        assert!(!console.animating());
+
+        console.write(b"\x1B[5mblinking\x1B[0m", false).unwrap();
+        console.redraw();
+        assert!(console.animating());
+
+        console.write(b"\x1B[2J", false).unwrap();
+        console.redraw();
+        assert!(!console.animating());
+
+        console.cursor_blink = true;
+        assert!(console.animating());
+        console.focused = false;
+        assert!(!console.animating());
+    }
+
+    #[test]
+    fn select_in_scrollback() {
+        let mut console = console();
+        for line in 0..100 {
+            let text = format!("line {}\r\n", line);
+            console.write(text.as_bytes(), false).unwrap();
+        }
+        console.scroll_view(50);
+        let top = console.line_text(console.top_line()).0;
+
+        // Drag over the first two rows of the view
+        let (width, height) = console.block_handler.get();
+        let (x, y) = console.block_handler.get_pixels_from_block(0, 0);
+        console.input(EventOption::Mouse(orbclient::MouseEvent {
+            x: x as i32,
+            y: y as i32,
+        }));
+        console.input(EventOption::Button(orbclient::ButtonEvent {
+            left: true,
+            middle: false,
+            right: false,
+        }));
+        console.input(EventOption::Mouse(orbclient::MouseEvent {
+            x: (x + width * 7) as i32,
+            y: (y + height) as i32,
+        }));
+
+        let text = console.selection_text();
+        assert_eq!(text.lines().next(), Some(top.trim_end()));
+        assert_eq!(text.lines().count(), 2);
+    }
+
+    #[test]
+    fn unused_clusters_are_dropped() {
+        let mut console = console();
+        console.scrollback_lines = 10;
+        // Every line has clusters that were not seen before, with an accent on
+        // each letter
+        for line in 0..200u32 {
+            let text: String = (0..20)
+                .map(|x| format!("{}\u{301}", char::from_u32(0x4E00 + line * 20 + x).unwrap()))
+                .collect();
+            console
+                .write(format!("{}\r\n", text).as_bytes(), false)
+                .unwrap();
+        }
+
+        assert!(console.clusters.len() < 2048);
+        let last = console.scrollback.len() + console.ransid.state.h - 1;
+        let expected: String = (0..20)
+            .map(|x| format!("{}\u{301}", char::from_u32(0x4E00 + 199 * 20 + x).unwrap()))
+            .collect();
+        assert_eq!(console.line_text(last).0.trim_end(), expected);
     }
 }
@@ -0,0 +1,95 @@
+//! Keeps track of which blocks have changed since the last frame was drawn
+
+use std::cmp;
+
+pub struct Damage {
+    w: usize,
+    h: usize,
+    /// The damaged columns of every row, as a half open range
+    rows: Vec<Option<(usize, usize)>>,
+    /// Set when everything, including the area outside of the grid, was damaged
+    full: bool,
+}
+
+impl Damage {
+    pub fn new(w: usize, h: usize) -> Self {
+        let mut damage = Damage {
+            w,
+            h,
+            rows: vec![None; h],
+            full: false,
+        };
+        damage.add_all();
+        damage
+    }
+
+    /// Change the size of the tracked grid, which damages all of it
+    pub fn resize(&mut self, w: usize, h: usize) {
+        self.w = w;
+        self.h = h;
+        self.rows = vec![None; h];
+        self.add_all();
+    }
+
+    /// Damage a rectangle of blocks, clipped to the grid
+    pub fn add(&mut self, x: usize, y: usize, w: usize, h: usize) {
+        let start_x = cmp::min(x, self.w);
+        let end_x = cmp::min(x.saturating_add(w), self.w);
+        if start_x >= end_x {
+            return;
+        }
+
+        let end_y = cmp::min(y.saturating_add(h), self.h);
+        for row in self.rows.iter_mut().take(end_y).skip(y) {
+            *row = Some(match *row {
+                Some((start, end)) => (cmp::min(start, start_x), cmp::max(end, end_x)),
+                None => (start_x, end_x),
+            });
+        }
+    }
+
+    pub fn add_all(&mut self) {
+        let (w, h) = (self.w, self.h);
+        self.add(0, 0, w, h);
+        self.full = true;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        !self.full && self.rows.iter().all(|row| row.is_none())
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.full
+    }
+
+    /// The damaged columns of each row, as (y, start_x, end_x)
+    pub fn rows(&self) -> impl Iterator<Item = (usize, usize, usize)> + '_ {
+        self.rows
+            .iter()
+            .enumerate()
+            .filter_map(|(y, row)| row.map(|(start, end)| (y, start, end)))
+    }
+
+    /// The damaged area as rectangles of blocks (x, y, w, h), with consecutive rows
+    /// that share the same columns merged together
+    pub fn rects(&self) -> Vec<(usize, usize, usize, usize)> {
+        let mut rects: Vec<(usize, usize, usize, usize)> = Vec::new();
+        for (y, start, end) in self.rows() {
+            if let Some(last) = rects.last_mut() {
+                if last.0 == start && last.2 == end - start && last.1 + last.3 == y {
+                    last.3 += 1;
+                    continue;
+                }
+            }
+            rects.push((start, y, end - start, 1));
+        }
+        rects
+    }
+
+    pub fn clear(&mut self) {
+        for row in self.rows.iter_mut() {
+            *row = None;
+        }
+        self.full = false;
+    }
+}
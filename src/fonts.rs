@@ -0,0 +1,345 @@
+//! Loading of the regular, bold, italic and bold italic font faces, and of the
+//! fallback fonts that are searched for glyphs those faces are missing
+
+use std::cmp;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use orbclient::Color;
+use orbfont::Font;
+use rusttype::{FontCollection, Scale};
+
+use config::Config;
+use glyph_cache::Mask;
+
+// Note that fonts can be located in either /usr/share/fonts/TTF or
+// /usr/share/fonts/truetype/ depending on the distro
+
+const FALLBACK_REGULAR_FONTS: [&str; 5] = [
+    "/usr/share/fonts/TTF/RobotoMono-Regular.ttf",
+    "/usr/share/fonts/TTF/DejaVuSansMono.ttf",
+    "/usr/share/fonts/truetype/dejavu/DejaVuSansMono.ttf",
+    "/usr/share/fonts/truetype/liberation/LiberationMono-Regular.ttf",
+    "/usr/share/fonts/truetype/ttf-dejavu/DejaVuSansMono.ttf",
+];
+
+const FALLBACK_BOLD_FONTS: [&str; 5] = [
+    "/usr/share/fonts/TTF/RobotoMono-Bold.ttf",
+    "/usr/share/fonts/TTF/DejaVuSansMono-Bold.ttf",
+    "/usr/share/fonts/truetype/dejavu/DejaVuSansMono.ttf",
+    "/usr/share/fonts/truetype/liberation/LiberationMono-Regular.ttf",
+    "/usr/share/fonts/truetype/ttf-dejavu/DejaVuSansMono.ttf",
+];
+
+const FALLBACK_ITALIC_FONTS: [&str; 4] = [
+    "/usr/share/fonts/TTF/RobotoMono-Italic.ttf",
+    "/usr/share/fonts/TTF/DejaVuSansMono-Oblique.ttf",
+    "/usr/share/fonts/truetype/dejavu/DejaVuSansMono-Oblique.ttf",
+    "/usr/share/fonts/truetype/ttf-dejavu/DejaVuSansMono-Oblique.ttf",
+];
+
+const FALLBACK_BOLD_ITALIC_FONTS: [&str; 4] = [
+    "/usr/share/fonts/TTF/RobotoMono-BoldItalic.ttf",
+    "/usr/share/fonts/TTF/DejaVuSansMono-BoldOblique.ttf",
+    "/usr/share/fonts/truetype/dejavu/DejaVuSansMono-BoldOblique.ttf",
+    "/usr/share/fonts/truetype/ttf-dejavu/DejaVuSansMono-BoldOblique.ttf",
+];
+
+/// Fonts with a wide coverage of symbols and scripts, searched after the fonts
+/// in `font_fallback` and the fonts found through `Font::find`
+const SYSTEM_FALLBACK_FONTS: [&str; 11] = [
+    "/usr/share/fonts/TTF/DejaVuSans.ttf",
+    "/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf",
+    "/usr/share/fonts/truetype/ttf-dejavu/DejaVuSans.ttf",
+    "/usr/share/fonts/noto/NotoSansSymbols2-Regular.ttf",
+    "/usr/share/fonts/truetype/noto/NotoSansSymbols2-Regular.ttf",
+    "/usr/share/fonts/noto/NotoSansSymbols-Regular.ttf",
+    "/usr/share/fonts/truetype/noto/NotoSansSymbols-Regular.ttf",
+    "/usr/share/fonts/TTF/Symbola.ttf",
+    "/usr/share/fonts/truetype/ancient-scripts/Symbola_hint.ttf",
+    "/usr/share/fonts/TTF/DroidSansFallbackFull.ttf",
+    "/usr/share/fonts/truetype/droid/DroidSansFallbackFull.ttf",
+];
+
+/// The typefaces and families looked up with `Font::find` when searching for a
+/// fallback font, where no family means the default family of the platform
+const FIND_FALLBACK_FONTS: [(&str, Option<&str>); 3] =
+    [("Sans", None), ("Sans", Some("Noto")), ("Serif", None)];
+
+/// A character no font has a glyph for, which is drawn as the undefined glyph
+const UNDEFINED: char = '\u{FFFF}';
+
+/// The font size characters are rendered at to compare them with the undefined
+/// glyph
+const PROBE_SIZE: f32 = 24.0;
+
+/// A font, along with what is needed to tell which characters it has glyphs for
+pub struct Face {
+    pub font: Font,
+    coverage: Coverage,
+}
+
+enum Coverage {
+    /// The font data, where glyphs and metrics are looked up
+    Data(rusttype::Font<'static>),
+    /// How the undefined glyph is rendered, which characters are compared with
+    /// as fonts found through `Font::find` do not give their data away
+    Rendered(Box<[u8]>),
+}
+
+impl Face {
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Face, String> {
+        let mut data = Vec::new();
+        File::open(path)
+            .and_then(|mut file| file.read_to_end(&mut data))
+            .map_err(|err| format!("failed to read font: {}", err))?;
+
+        // Both orbfont and the glyph lookup share the same data
+        let data = Arc::new(data.into_boxed_slice());
+        let font = Font::from_data(data.clone())?;
+        let info = FontCollection::from_bytes(data)
+            .into_font()
+            .ok_or_else(|| "font collection did not have exactly one font".to_string())?;
+
+        Ok(Face {
+            font,
+            coverage: Coverage::Data(info),
+        })
+    }
+
+    /// The font `Font::find` finds for the typeface, family and style
+    pub fn find(typeface: &str, family: Option<&str>, style: &str) -> Result<Face, String> {
+        let font = Font::find(Some(typeface), family, Some(style))?;
+        let undefined = render(&font, UNDEFINED);
+        Ok(Face {
+            font,
+            coverage: Coverage::Rendered(undefined),
+        })
+    }
+
+    /// Whether the font has a glyph for the character, rather than only the
+    /// undefined glyph that is drawn as a box
+    pub fn has_glyph(&self, c: char) -> bool {
+        match self.coverage {
+            Coverage::Data(ref info) => info.glyph(c).is_some_and(|glyph| glyph.id().0 != 0),
+            Coverage::Rendered(ref undefined) => render(&self.font, c) != *undefined,
+        }
+    }
+
+    /// The advance of a character and the height of a line of text, along with
+    /// the gap between lines, at the font size
+    fn measure(&self, font_size: f32) -> (f32, f32, f32) {
+        match self.coverage {
+            Coverage::Data(ref info) => {
+                let scale = Scale::uniform(font_size);
+                let v_metrics = info.v_metrics(scale);
+                // Every glyph of a monospace font has the same advance
+                let advance = info
+                    .glyph('M')
+                    .map(|glyph| glyph.scaled(scale).h_metrics().advance_width)
+                    .unwrap_or(font_size / 2.0);
+                (
+                    advance,
+                    v_metrics.ascent - v_metrics.descent,
+                    v_metrics.line_gap,
+                )
+            }
+            Coverage::Rendered(_) => {
+                // Rendered text is only measured in whole pixels, so the advance
+                // is taken over ten characters
+                let one = self.font.render("M", font_size);
+                let eleven = self.font.render("MMMMMMMMMMM", font_size);
+                let advance = eleven.width().saturating_sub(one.width()) as f32 / 10.0;
+                (advance, one.height() as f32, 0.0)
+            }
+        }
+    }
+}
+
+/// The coverage of the character rendered in the font
+fn render(font: &Font, c: char) -> Box<[u8]> {
+    let mut str_buf = [0; 4];
+    let text = font.render(c.encode_utf8(&mut str_buf), PROBE_SIZE);
+    let mut mask = Mask::new(text.width() as usize, text.height() as usize);
+    text.draw(&mut mask, 0, 0, Color { data: 0xFFFFFFFF });
+    mask.coverage()
+}
+
+/// A fallback font, which is only loaded once a glyph has to be searched for
+enum Fallback {
+    Path(PathBuf),
+    Find(&'static str, Option<&'static str>),
+}
+
+impl Fallback {
+    fn load(&self) -> Result<Face, String> {
+        match *self {
+            Fallback::Path(ref path) => Face::from_path(path),
+            Fallback::Find(typeface, family) => Face::find(typeface, family, "Regular"),
+        }
+    }
+}
+
+/// The size of a block at some font size, and where text is placed in it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Metrics {
+    /// The distance from the top of the highest glyph to the bottom of the
+    /// lowest glyph, in pixels
+    pub font_size: f32,
+    pub block_width: usize,
+    pub block_height: usize,
+    /// Where the text starts relative to the top left of its block
+    pub glyph_x: i32,
+    pub glyph_y: i32,
+}
+
+pub struct Fonts {
+    pub regular: Face,
+    pub bold: Face,
+    /// Not every monospace font ships italic faces, in which case the upright
+    /// faces are used instead
+    pub italic: Option<Face>,
+    pub bold_italic: Option<Face>,
+    /// Every font that may be searched for glyphs the faces above are missing,
+    /// in order
+    fallback_fonts: Vec<Fallback>,
+    /// The fallback fonts loaded so far. They are only loaded once a glyph has to
+    /// be searched for, as there is no use in reading them otherwise
+    fallbacks: Vec<Option<Face>>,
+    /// The index of the fallback font used for each character searched for so
+    /// far, or `None` if none of them have it
+    fallback_chars: HashMap<char, Option<usize>>,
+}
+
+impl Fonts {
+    pub fn load(config: &Config) -> Fonts {
+        let regular = load_face(&config.font, "Regular", &FALLBACK_REGULAR_FONTS)
+            .expect("Could not find a regular monospace font");
+        let bold = load_face(&config.font_bold, "Bold", &FALLBACK_BOLD_FONTS)
+            .expect("Could not find a bold monospace font");
+        let italic = load_face(&config.font_italic, "Italic", &FALLBACK_ITALIC_FONTS);
+        let bold_italic = load_face(
+            &config.font_bold_italic,
+            "BoldItalic",
+            &FALLBACK_BOLD_ITALIC_FONTS,
+        );
+
+        let mut fallback_fonts: Vec<Fallback> = config
+            .font_fallback
+            .iter()
+            .flatten()
+            .map(|path| Fallback::Path(PathBuf::from(path)))
+            .collect();
+        fallback_fonts.extend(
+            FIND_FALLBACK_FONTS
+                .iter()
+                .map(|&(typeface, family)| Fallback::Find(typeface, family)),
+        );
+        fallback_fonts.extend(
+            SYSTEM_FALLBACK_FONTS
+                .iter()
+                .map(|path| Fallback::Path(PathBuf::from(path))),
+        );
+
+        Fonts {
+            regular,
+            bold,
+            italic,
+            bold_italic,
+            fallback_fonts,
+            fallbacks: Vec::new(),
+            fallback_chars: HashMap::new(),
+        }
+    }
+
+    /// The block size for the regular face at the given font size. Blocks are as
+    /// wide as the advance of the face plus `letter_spacing` pixels, and as tall
+    /// as its line height times `line_height`, with the text centred in them
+    pub fn metrics(&self, font_size: f32, line_height: f32, letter_spacing: f32) -> Metrics {
+        let (advance, text_height, line_gap) = self.regular.measure(font_size);
+        let block_width = cmp::max(1, (advance + letter_spacing).round() as i32) as usize;
+        let block_height =
+            cmp::max(1, ((text_height + line_gap) * line_height).round() as i32) as usize;
+
+        Metrics {
+            font_size,
+            block_width,
+            block_height,
+            glyph_x: (letter_spacing / 2.0).round() as i32,
+            glyph_y: ((block_height as f32 - text_height) / 2.0).round() as i32,
+        }
+    }
+
+    /// The face to draw text with the given style in
+    fn face(&self, bold: bool, italic: bool) -> &Face {
+        match (bold, italic) {
+            (false, false) => &self.regular,
+            (true, false) => &self.bold,
+            (false, true) => self.italic.as_ref().unwrap_or(&self.regular),
+            (true, true) => self
+                .bold_italic
+                .as_ref()
+                .or(self.italic.as_ref())
+                .unwrap_or(&self.bold),
+        }
+    }
+
+    /// The font to draw a character with the given style in, which is a fallback
+    /// font if the face for that style does not have it
+    pub fn get(&mut self, c: char, bold: bool, italic: bool) -> &Font {
+        if self.face(bold, italic).has_glyph(c) || c.is_whitespace() {
+            return &self.face(bold, italic).font;
+        }
+
+        match self.fallback(c) {
+            Some(index) => &self.fallbacks[index].as_ref().unwrap().font,
+            None => &self.face(bold, italic).font,
+        }
+    }
+
+    /// Search the fallback fonts for the character, remembering the result
+    fn fallback(&mut self, c: char) -> Option<usize> {
+        if let Some(&index) = self.fallback_chars.get(&c) {
+            return index;
+        }
+
+        let mut found = None;
+        for index in 0..self.fallback_fonts.len() {
+            if index == self.fallbacks.len() {
+                self.fallbacks.push(self.fallback_fonts[index].load().ok());
+            }
+
+            if self.fallbacks[index]
+                .as_ref()
+                .is_some_and(|face| face.has_glyph(c))
+            {
+                found = Some(index);
+                break;
+            }
+        }
+
+        self.fallback_chars.insert(c, found);
+        found
+    }
+}
+
+/// Load the font configured by the user, or search the system for one in the
+/// requested style
+fn load_face(path: &Option<String>, style: &str, fallbacks: &[&str]) -> Option<Face> {
+    if let Some(font_path) = path {
+        return Some(
+            Face::from_path(font_path)
+                .unwrap_or_else(|err| panic!("Failed to load custom font {}: {}", font_path, err)),
+        );
+    }
+
+    Face::find("Mono", None, style).ok().or_else(|| {
+        // Try the fallback fonts
+        fallbacks
+            .iter()
+            .find_map(|fallback| Face::from_path(fallback).ok())
+    })
+}
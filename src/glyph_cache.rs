@@ -0,0 +1,216 @@
+//! A cache of rasterized glyphs, so that every character only has to go through
+//! orbfont once for a given style and block size
+
+use std::cell::Cell;
+use std::collections::HashMap;
+
+use orbclient::{Color, Mode, Renderer};
+use orbfont::Font;
+
+use box_drawing;
+use fonts::{Fonts, Metrics};
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct GlyphKey {
+    pub c: char,
+    /// The index of the grapheme cluster starting with `c`, or 0 for `c` alone
+    pub cluster: u32,
+    pub bold: bool,
+    pub italic: bool,
+}
+
+/// The coverage mask of a single rasterized glyph, one alpha value per pixel
+pub struct Glyph {
+    width: usize,
+    height: usize,
+    coverage: Box<[u8]>,
+    empty: bool,
+}
+
+impl Glyph {
+    fn rasterize(font: &Font, text: &str, metrics: Metrics) -> Glyph {
+        let text = font.render(text, metrics.font_size);
+
+        // The glyph covers the whole height of the block, with the text placed
+        // inside it as the metrics say
+        let width = (text.width() as i32 + metrics.glyph_x).max(0) as usize;
+        let mut mask = Mask::new(width, metrics.block_height);
+        text.draw(
+            &mut mask,
+            metrics.glyph_x,
+            metrics.glyph_y,
+            Color { data: 0xFFFFFFFF },
+        );
+
+        Glyph::from_coverage(mask.width, mask.height, mask.coverage())
+    }
+
+    /// A glyph that was drawn some other way than through a font
+    pub fn from_coverage(width: usize, height: usize, coverage: Box<[u8]>) -> Glyph {
+        let empty = coverage.iter().all(|&alpha| alpha == 0);
+        Glyph {
+            width,
+            height,
+            coverage,
+            empty,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// The coverage of a single pixel of the glyph
+    #[cfg(test)]
+    pub fn alpha(&self, x: usize, y: usize) -> u8 {
+        self.coverage[y * self.width + x]
+    }
+
+    /// Blend the glyph onto the renderer using the provided colour
+    pub fn draw<R: Renderer>(&self, renderer: &mut R, x: i32, y: i32, color: Color) {
+        if self.empty {
+            return;
+        }
+
+        let rgb = color.data & 0xFFFFFF;
+        for glyph_y in 0..self.height {
+            let row = &self.coverage[glyph_y * self.width..(glyph_y + 1) * self.width];
+            for (glyph_x, &alpha) in row.iter().enumerate() {
+                if alpha > 0 {
+                    renderer.pixel(
+                        x + glyph_x as i32,
+                        y + glyph_y as i32,
+                        Color {
+                            data: ((alpha as u32) << 24) | rgb,
+                        },
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// An offscreen renderer that records the coverage orbfont produces for a glyph
+pub struct Mask {
+    width: usize,
+    height: usize,
+    data: Vec<Color>,
+    mode: Cell<Mode>,
+}
+
+impl Mask {
+    pub fn new(width: usize, height: usize) -> Mask {
+        Mask {
+            width,
+            height,
+            data: vec![Color { data: 0 }; width * height],
+            mode: Cell::new(Mode::Overwrite),
+        }
+    }
+
+    /// The alpha value of every pixel
+    pub fn coverage(&self) -> Box<[u8]> {
+        self.data.iter().map(|color| color.a()).collect()
+    }
+}
+
+impl Renderer for Mask {
+    fn width(&self) -> u32 {
+        self.width as u32
+    }
+
+    fn height(&self) -> u32 {
+        self.height as u32
+    }
+
+    fn data(&self) -> &[Color] {
+        &self.data
+    }
+
+    fn data_mut(&mut self) -> &mut [Color] {
+        &mut self.data
+    }
+
+    fn sync(&mut self) -> bool {
+        true
+    }
+
+    fn update(&mut self) -> bool {
+        true
+    }
+
+    fn update_rects(&mut self, _rects: &[(i32, i32, u32, u32)]) -> bool {
+        true
+    }
+
+    fn mode(&self) -> &Cell<Mode> {
+        &self.mode
+    }
+
+    fn pixel(&mut self, x: i32, y: i32, color: Color) {
+        if x >= 0 && y >= 0 && (x as usize) < self.width && (y as usize) < self.height {
+            // Overlapping glyph contours keep the strongest coverage
+            let pixel = &mut self.data[y as usize * self.width + x as usize];
+            if color.a() > pixel.a() {
+                *pixel = Color {
+                    data: (color.data & 0xFF000000) | 0xFFFFFF,
+                };
+            }
+        }
+    }
+}
+
+/// Every glyph in the cache is rasterized with the same metrics
+pub struct GlyphCache {
+    glyphs: HashMap<GlyphKey, Glyph>,
+    metrics: Metrics,
+}
+
+impl GlyphCache {
+    pub fn new(metrics: Metrics) -> Self {
+        GlyphCache {
+            glyphs: HashMap::new(),
+            metrics,
+        }
+    }
+
+    /// Glyphs rendered for another font size will never be used again, so they
+    /// are dropped as soon as the metrics change
+    pub fn set_metrics(&mut self, metrics: Metrics) {
+        if metrics != self.metrics {
+            self.metrics = metrics;
+            self.clear();
+        }
+    }
+
+    /// Forget every rasterized glyph, needed when the metrics change or the
+    /// fonts are reloaded
+    pub fn clear(&mut self) {
+        self.glyphs.clear();
+    }
+
+    /// Forget the glyphs of grapheme clusters, needed when the clusters are
+    /// given new indices
+    pub fn clear_clusters(&mut self) {
+        self.glyphs.retain(|key, _| key.cluster == 0);
+    }
+
+    /// The glyph for the key, rasterizing the text on first use with the font
+    /// the fonts pick for it, so that glyphs already in the cache do not go
+    /// through the fallback fonts. Lines and blocks are drawn by orbterm itself
+    /// instead, unless something was combined with them
+    pub fn get(&mut self, fonts: &mut Fonts, text: &str, key: GlyphKey) -> &Glyph {
+        let metrics = self.metrics;
+        self.glyphs.entry(key).or_insert_with(|| {
+            let builtin = if key.cluster == 0 {
+                box_drawing::rasterize(key.c, metrics.block_width, metrics.block_height)
+            } else {
+                None
+            };
+            builtin.unwrap_or_else(|| {
+                let font = fonts.get(key.c, key.bold, key.italic);
+                Glyph::rasterize(font, text, metrics)
+            })
+        })
+    }
+}
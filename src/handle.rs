@@ -4,7 +4,26 @@ use std::io::{self, ErrorKind, Read, Write};
 use std::os::unix::io::{FromRawFd, RawFd};
 use std::process::Child;
 
-use console::Console;
+use orbterm::console::Console;
+
+/// How often the console is woken up to blink the cursor and text, in
+/// nanoseconds
+#[cfg(target_os = "redox")]
+const TICK_NS: i32 = 50_000_000;
+
+/// Ask for a time event after the next tick
+#[cfg(target_os = "redox")]
+fn schedule_tick(time_file: &mut File) {
+    let mut time = syscall::data::TimeSpec::default();
+    if time_file.read(&mut time).is_ok() {
+        time.tv_nsec += TICK_NS;
+        if time.tv_nsec >= 1_000_000_000 {
+            time.tv_sec += 1;
+            time.tv_nsec -= 1_000_000_000;
+        }
+        let _ = time_file.write(&time);
+    }
+}
 
 #[cfg(target_os = "redox")]
 pub fn handle(console: &mut Console, master_fd: RawFd, process: &mut Child) {
@@ -26,6 +45,18 @@ pub fn handle(console: &mut Console, master_fd: RawFd, process: &mut Child) {
         data: 0
     }).expect("terminal: failed to fevent master PTY");
 
+    let mut time_file = File::open(&format!("time:{}", syscall::flag::CLOCK_MONOTONIC))
+        .expect("terminal: failed to open time file");
+    let time_fd = time_file.as_raw_fd();
+    event_file.write(&syscall::data::Event {
+        id: time_fd as usize,
+        flags: syscall::flag::EVENT_READ,
+        data: 0
+    }).expect("terminal: failed to fevent time");
+
+    // Whether a time event has been asked for and not handled yet
+    let mut tick_scheduled = false;
+
     let mut handle_event = |event_id: usize| -> bool {
         if event_id == window_fd as usize {
             for event in console.window.events() {
@@ -65,6 +96,12 @@ pub fn handle(console: &mut Console, master_fd: RawFd, process: &mut Child) {
                     console.redraw();
                 }
             }
+            // Output has stopped for now, draw what is left over from the last frame
+            console.redraw();
+        } else if event_id == time_fd as usize {
+            // The cursor and text blink even while nothing else happens
+            tick_scheduled = false;
+            console.redraw();
         } else {
             println!("Unknown event {}", event_id);
         }
@@ -80,11 +117,18 @@ pub fn handle(console: &mut Console, master_fd: RawFd, process: &mut Child) {
             console.input.clear();
         }
 
+        // Only wake up again while something changes with time
+        if ! tick_scheduled && console.animating() {
+            schedule_tick(&mut time_file);
+            tick_scheduled = true;
+        }
+
         true
     };
 
     handle_event(window_fd as usize);
     handle_event(master_fd as usize);
+    handle_event(time_fd as usize);
 
     'events: loop {
         let mut sys_event = syscall::Event::default();
@@ -151,10 +195,10 @@ pub fn handle(console: &mut Console, master_fd: RawFd, process: &mut Child) {
             },
             Ok(count) => {
                 console.write(&packet[..count], true).expect("terminal: failed to write to console");
-                console.redraw();
             },
             Err(err) => match err.kind() {
-                ErrorKind::WouldBlock => (),
+                // Output has stopped for now, draw what is left over from the last frame
+                ErrorKind::WouldBlock => console.redraw(),
                 _ => panic!("terminal: failed to read master PTY: {:?}", err)
             }
         }
@@ -171,12 +215,10 @@ pub fn handle(console: &mut Console, master_fd: RawFd, process: &mut Child) {
         }
 
         match process.try_wait() {
-            Ok(status) => match status {
-                Some(_code) => {
-                    break 'events;
-                },
-                None => ()
+            Ok(Some(_code)) => {
+                break 'events;
             },
+            Ok(None) => (),
             Err(err) => match err.kind() {
                 ErrorKind::WouldBlock => (),
                 _ => panic!("terminal: failed to wait on child: {:?}", err)
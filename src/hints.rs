@@ -0,0 +1,80 @@
+//! Hints label the matches of patterns on the grid, so that a match can be
+//! copied or opened by typing its label
+
+use patterns::Match;
+
+/// The letters labels are made of, easiest to reach first
+pub const ALPHABET: &str = "asdfghjklqwertyuiopzxcvbnm";
+
+pub struct Hint {
+    pub label: String,
+    pub found: Match,
+}
+
+pub struct Hints {
+    pub hints: Vec<Hint>,
+    /// The start of a label typed so far
+    pub typed: String,
+}
+
+impl Hints {
+    /// Label the matches. Every label has the same length, so that none of them
+    /// is the start of another
+    pub fn new(matches: Vec<Match>) -> Hints {
+        let letters: Vec<char> = ALPHABET.chars().collect();
+        let mut length = 1;
+        let mut count = letters.len();
+        while count < matches.len() {
+            length += 1;
+            count *= letters.len();
+        }
+
+        let hints = matches
+            .into_iter()
+            .enumerate()
+            .map(|(index, found)| {
+                let mut label = Vec::with_capacity(length);
+                let mut rest = index;
+                for _ in 0..length {
+                    label.push(letters[rest % letters.len()]);
+                    rest /= letters.len();
+                }
+                Hint {
+                    label: label.into_iter().rev().collect(),
+                    found,
+                }
+            })
+            .collect();
+
+        Hints {
+            hints,
+            typed: String::new(),
+        }
+    }
+
+    /// The hints whose labels start with what has been typed so far
+    pub fn visible(&self) -> impl Iterator<Item = &Hint> {
+        self.hints
+            .iter()
+            .filter(move |hint| hint.label.starts_with(&self.typed))
+    }
+
+    /// The visible hint covering the block, if any
+    pub fn at(&self, x: usize, y: usize) -> Option<&Hint> {
+        self.visible()
+            .find(|hint| hint.found.y == y && hint.found.start <= x && x < hint.found.end)
+    }
+
+    /// Add a letter to the label typed so far, unless no label starts with it.
+    /// Returns the hint whose label has been typed in full
+    pub fn push(&mut self, c: char) -> Option<&Hint> {
+        self.typed.push(c);
+        if self.visible().next().is_none() {
+            self.typed.pop();
+            return None;
+        }
+
+        let typed = &self.typed;
+        self.hints.iter().find(|hint| &hint.label == typed)
+    }
+}
@@ -0,0 +1,38 @@
+#[macro_use]
+extern crate serde_derive;
+extern crate failure;
+#[cfg(not(target_os = "redox"))]
+extern crate libc;
+extern crate orbclient;
+extern crate orbfont;
+extern crate regex;
+extern crate rusttype;
+extern crate toml;
+extern crate unicode_width;
+extern crate vte;
+extern crate xdg;
+
+pub use config::Config;
+
+pub mod block_handler;
+pub mod box_drawing;
+pub mod clusters;
+pub mod config;
+pub mod console;
+pub mod damage;
+pub mod fonts;
+pub mod glyph_cache;
+pub mod hints;
+pub mod open;
+pub mod parser;
+pub mod patterns;
+pub mod render_target;
+pub mod search;
+pub mod vi;
+
+/// The size of the font in points, when none is configured
+pub const DEFAULT_FONT_SIZE: f32 = 12.0;
+
+/// The resolution that displays are assumed to have, before accounting for
+/// high resolution displays
+pub const DEFAULT_DPI: f32 = 96.0;
@@ -1,12 +1,7 @@
-#[macro_use]
-extern crate serde_derive;
 #[cfg(feature = "env_logger")]
 extern crate env_logger;
-extern crate failure;
 extern crate orbclient;
-extern crate orbfont;
-extern crate toml;
-extern crate xdg;
+extern crate orbterm;
 
 #[cfg(not(target_os = "redox"))]
 extern crate libc;
@@ -20,35 +15,64 @@ extern crate syscall;
 use std::io::Write;
 use std::os::unix::io::{AsRawFd, FromRawFd};
 use std::os::unix::process::CommandExt;
+use std::path::PathBuf;
 use std::process::{Command, Stdio};
-use std::{cmp, env, io};
+use std::{env, io};
 
 use before_exec::before_exec;
-use config::Config;
-use console::Console;
 use getpty::getpty;
 use handle::handle;
+use orbterm::console::Console;
+use orbterm::Config;
 use slave_stdio::slave_stdio;
 
 mod before_exec;
-mod block_handler;
-mod config;
-mod console;
 mod getpty;
 mod handle;
 mod slave_stdio;
 
-pub const BLOCK_WIDTH: u32 = 8;
-pub const BLOCK_HEIGHT: u32 = BLOCK_WIDTH * 2;
-
 const DEFAULT_INITIAL_WIDTH: u32 = 80;
 const DEFAULT_INITIAL_HEIGHT: u32 = 24;
 
+/// Use the orbterm terminfo entry from res/orbterm.terminfo when it has been
+/// installed, otherwise fall back to xterm-256color
+fn term() -> &'static str {
+    let mut dirs: Vec<PathBuf> = Vec::new();
+    if let Some(dir) = env::var_os("TERMINFO") {
+        dirs.push(dir.into());
+    }
+    if let Some(home) = env::var_os("HOME") {
+        dirs.push(PathBuf::from(home).join(".terminfo"));
+    }
+    if let Some(terminfo_dirs) = env::var_os("TERMINFO_DIRS") {
+        dirs.extend(env::split_paths(&terminfo_dirs));
+    }
+    for dir in &[
+        "/etc/terminfo",
+        "/lib/terminfo",
+        "/usr/share/terminfo",
+        "/usr/lib/terminfo",
+    ] {
+        dirs.push(dir.into());
+    }
+
+    // Entries are either stored under their first letter, or its hex code
+    let installed = dirs.iter().any(|dir| {
+        dir.join("o").join("orbterm").exists() || dir.join("6f").join("orbterm").exists()
+    });
+
+    if installed {
+        "orbterm"
+    } else {
+        "xterm-256color"
+    }
+}
+
 fn main() {
     #[cfg(feature = "env_logger")]
     env_logger::init();
 
-    let config = match Config::load() {
+    let mut config = match Config::load() {
         Ok(config) => config,
         Err(err) => {
             eprintln!("orbterm: failed to open config: {}", err);
@@ -56,7 +80,19 @@ fn main() {
         }
     };
 
-    let mut args = env::args().skip(1);
+    let mut args = env::args().skip(1).peekable();
+
+    // orbterm [--title TITLE] [SHELL [ARGS...]]
+    if args.peek().map(String::as_str) == Some("--title") {
+        args.next();
+        match args.next() {
+            Some(title) => config.title = Some(title),
+            None => {
+                eprintln!("orbterm: --title needs a title");
+                return;
+            }
+        }
+    }
 
     let user_specified_shell = args.next();
     let system_shell = env::var("SHELL").unwrap_or("/bin/sh".to_string());
@@ -85,8 +121,8 @@ fn main() {
         .env("LINES", "")
         // It is useful to know if we are running inside of orbterm, some times
         .env("ORBTERM_VERSION", env!("CARGO_PKG_VERSION"))
-        // We emulate xterm-256color
-        .env("TERM", "xterm-256color")
+        // We emulate xterm-256color, plus a few extensions
+        .env("TERM", term())
         .env("TTY", tty_path);
 
     unsafe {
@@ -94,7 +130,7 @@ fn main() {
             .stdin(Stdio::from_raw_fd(slave_stdin.as_raw_fd()))
             .stdout(Stdio::from_raw_fd(slave_stdout.as_raw_fd()))
             .stderr(Stdio::from_raw_fd(slave_stderr.as_raw_fd()))
-            .pre_exec(|| before_exec());
+            .pre_exec(before_exec);
     }
 
     match command.spawn() {
@@ -103,21 +139,11 @@ fn main() {
             drop(slave_stdout);
             drop(slave_stdin);
 
-            let scale = config
-                .get_initial_scale(display_height)
-                .expect("Failed to retrieve the default scale");
-            let (block_width, block_height) = (
-                (BLOCK_WIDTH as f32 * scale) as u32,
-                (BLOCK_HEIGHT as f32 * scale) as u32,
-            );
-
-            let mut console = Console::new(
-                &config,
-                columns * block_width as u32,
-                rows * block_height as u32,
-                block_width as usize,
-                block_height as usize,
-            );
+            let zoom = config
+                .get_initial_zoom(display_height)
+                .expect("Failed to retrieve the default zoom");
+            let dpi = config.get_dpi(display_height);
+            let mut console = Console::new(&config, columns, rows, dpi, zoom);
 
             handle(&mut console, master_fd, &mut process);
         }
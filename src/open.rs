@@ -0,0 +1,66 @@
+//! Opening links and files with another program, and new windows
+
+use std::env;
+use std::path::Path;
+use std::process::Command;
+use std::thread;
+
+/// The command used when none is configured
+#[cfg(target_os = "redox")]
+pub const DEFAULT_OPENER: &str = "launcher";
+#[cfg(not(target_os = "redox"))]
+pub const DEFAULT_OPENER: &str = "xdg-open";
+
+/// Run the command, which may include arguments of its own, with the target as
+/// its last argument. The command is not run through a shell, so the target is
+/// passed on as it is
+pub fn open(command: &str, target: &str, cwd: Option<&Path>) {
+    let mut args: Vec<String> = command.split_whitespace().map(String::from).collect();
+    args.push(target.to_string());
+    spawn(&args, cwd);
+}
+
+/// Run the program named by the first argument with the rest of them, without
+/// waiting for it to finish. It is run in the working directory of the shell
+/// when known, which relative paths it is given are relative to
+pub fn spawn(args: &[String], cwd: Option<&Path>) {
+    let (program, args) = match args.split_first() {
+        Some(split) => split,
+        None => return,
+    };
+
+    let mut command = Command::new(program);
+    command.args(args);
+    if let Some(cwd) = cwd.filter(|cwd| cwd.is_dir()) {
+        command.current_dir(cwd);
+    }
+    run(&mut command, program);
+}
+
+/// Start another orbterm, in the given directory when it still exists
+pub fn new_window(cwd: Option<&Path>) {
+    let exe = match env::current_exe() {
+        Ok(exe) => exe,
+        Err(err) => {
+            eprintln!("orbterm: failed to find the orbterm executable: {}", err);
+            return;
+        }
+    };
+
+    let mut command = Command::new(&exe);
+    if let Some(cwd) = cwd.filter(|cwd| cwd.is_dir()) {
+        command.current_dir(cwd);
+    }
+    run(&mut command, &exe.display().to_string());
+}
+
+/// Start the command, and wait for it to exit on a thread of its own so that it
+/// does not stay around as a zombie
+fn run(command: &mut Command, name: &str) {
+    match command.spawn() {
+        Ok(mut child) => {
+            thread::spawn(move || child.wait());
+        }
+        Err(err) => eprintln!("orbterm: failed to run '{}': {}", name, err),
+    }
+}
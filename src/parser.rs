@@ -0,0 +1,1145 @@
+//! Parses the output of the shell. Most sequences are left to ransid, but the
+//! graphic rendition is kept here, as ransid only knows about a few of the
+//! attributes that programs use
+
+use config::{color_spec, decode_hex, parse_color};
+use console::ransid::{self, Color, State};
+use std::ffi::OsStr;
+#[cfg(target_os = "redox")]
+use std::fs;
+use std::os::unix::ffi::OsStrExt;
+use std::path::PathBuf;
+use std::str;
+use unicode_width::UnicodeWidthChar;
+use vte::{Params, ParamsIter, Perform};
+
+/// The ways text can be underlined, selected with `CSI 4:N m`
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Underline {
+    #[default]
+    None,
+    Single,
+    Double,
+    Curly,
+    Dotted,
+    Dashed,
+}
+
+/// The shapes the cursor can be drawn in, selected with `CSI Ps SP q`
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CursorShape {
+    Block,
+    Underline,
+    Bar,
+}
+
+/// The graphic rendition attributes a character was printed with
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Style {
+    pub bold: bool,
+    pub dim: bool,
+    pub italic: bool,
+    pub underline: Underline,
+    pub blink: bool,
+    pub inverse: bool,
+    pub hidden: bool,
+    pub strikethrough: bool,
+}
+
+/// A part of the command line marked with OSC 133
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum PromptMark {
+    /// The prompt starts
+    Prompt,
+    /// The command typed at the prompt starts
+    Command,
+    /// The output of the command starts
+    Output,
+    /// The command finished, with its exit status if it was given
+    Finished(Option<i32>),
+}
+
+/// The terminfo capabilities XTGETTCAP answers, which are the name, the colours
+/// and the extensions in res/orbterm.terminfo. Booleans have no value
+const CAPABILITIES: [(&str, Option<&str>); 9] = [
+    ("TN", Some("orbterm")),
+    ("Co", Some("256")),
+    ("colors", Some("256")),
+    ("RGB", Some("8/8/8")),
+    ("Su", None),
+    ("Smulx", Some("\x1B[4:%p1%dm")),
+    (
+        "Setulc",
+        Some("\x1B[58:2::%p1%{65536}%/%d:%p1%{256}%/%{255}%&%d:%p1%{255}%&%dm"),
+    ),
+    ("Ss", Some("\x1B[%p1%d q")),
+    ("Se", Some("\x1B[0 q")),
+];
+
+/// The longest device control string that is kept, as only short queries are
+/// answered
+const MAX_DCS_LEN: usize = 1024;
+
+/// A colour as it was given to a block, which is looked up in the palette and
+/// the default colours when the block is drawn, so that blocks follow changes
+/// to them
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum CellColor {
+    /// The default foreground or background
+    #[default]
+    Default,
+    Palette(u8),
+    Rgb(u8, u8, u8),
+}
+
+/// A colour that programs can query and change with OSC 4 and 10 to 12
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ColorSlot {
+    Palette(u8),
+    Foreground,
+    Background,
+    Cursor,
+}
+
+pub enum Event<'a> {
+    /// A character was printed at the given block
+    Char {
+        x: usize,
+        y: usize,
+        c: char,
+        color: CellColor,
+        style: Style,
+        /// Set when the underline has a colour of its own, rather than the colour
+        /// of the text
+        underline_color: Option<CellColor>,
+        /// Wide characters take up this block and the spacer after it
+        wide: bool,
+    },
+    /// The block after a wide character, which is covered by it
+    Spacer { x: usize, y: usize },
+    /// A zero width character, or a character after a zero width joiner, that
+    /// belongs to the grapheme cluster in the given block
+    Combine { x: usize, y: usize, c: char },
+    /// The application chose how the cursor is drawn, or asked for the cursor
+    /// the user configured when `shape` is `None`
+    CursorShape {
+        shape: Option<CursorShape>,
+        blink: bool,
+    },
+    /// Characters printed from now on link to the URI, or to nothing when `uri`
+    /// is `None`
+    Link { uri: Option<String> },
+    /// A part of the command line starts on row `y`
+    Mark { y: usize, mark: PromptMark },
+    /// The shell changed its working directory
+    Cwd { path: PathBuf },
+    /// Save the title on the title stack, or restore the last title saved
+    TitleStack { push: bool },
+    /// A program asked for the size of the grid in pixels, or of a block when
+    /// `block` is set, with `CSI 14 t` and `CSI 16 t`
+    SizeQuery { block: bool },
+    /// A program asked whether the alternate screen is on with DECRQM, as
+    /// `CSI ? mode $ p`
+    AlternateScreenQuery { mode: u16 },
+    /// Blocks were cleared to the given background
+    Rect {
+        x: usize,
+        y: usize,
+        w: usize,
+        h: usize,
+        color: CellColor,
+    },
+    /// A colour changed, so blocks drawn in it have to be drawn again
+    Recolor { slot: ColorSlot },
+    /// Any other event, as reported by ransid
+    Ransid(ransid::Event<'a>),
+}
+
+#[derive(Default)]
+pub struct Parser {
+    parser: vte::Parser,
+    pub style: Style,
+    /// The colours characters are printed in and blocks are cleared to
+    pub foreground: CellColor,
+    pub background: CellColor,
+    pub underline_color: Option<CellColor>,
+    /// Whether the application asked to be told when the window gains or loses
+    /// focus, with `CSI ? 1004 h`
+    pub focus_reporting: bool,
+    /// The colours of the palette that programs changed with OSC 4, by index
+    pub palette: Vec<Option<Color>>,
+    /// The colour of the cursor set with OSC 12, which is drawn by inverting
+    /// the block under it when unset
+    pub cursor_color: Option<Color>,
+    /// The default foreground and background from before programs changed
+    /// them, which OSC 110 and 111 go back to
+    initial_colors: Option<(Color, Color)>,
+    /// The XTGETTCAP query being received, as `DCS + q names ST`
+    dcs: Option<Vec<u8>>,
+    /// Set after a zero width joiner, when the next character joins the cluster
+    /// before it
+    join: bool,
+}
+
+impl Parser {
+    pub fn new() -> Self {
+        Parser::default()
+    }
+
+    /// The colour, as changed by programs that changed the palette
+    pub fn resolve(&self, color: Color) -> Color {
+        resolve(&self.palette, color)
+    }
+
+    /// The colour a block given `color` is drawn in, where the default is the
+    /// default background when `background` is set
+    pub fn cell_color(&self, state: &State, color: CellColor, background: bool) -> Color {
+        self.resolve(match color {
+            CellColor::Default if background => state.background_default,
+            CellColor::Default => state.foreground_default,
+            CellColor::Palette(index) => Color::Ansi(index),
+            CellColor::Rgb(r, g, b) => Color::TrueColor(r, g, b),
+        })
+    }
+
+    pub fn advance<F: FnMut(Event)>(&mut self, state: &mut State, bytes: &[u8], mut callback: F) {
+        let mut performer = Performer {
+            state,
+            style: &mut self.style,
+            foreground: &mut self.foreground,
+            background: &mut self.background,
+            underline_color: &mut self.underline_color,
+            focus_reporting: &mut self.focus_reporting,
+            palette: &mut self.palette,
+            cursor_color: &mut self.cursor_color,
+            initial_colors: &mut self.initial_colors,
+            dcs: &mut self.dcs,
+            join: &mut self.join,
+            callback: &mut callback,
+        };
+        self.parser.advance(&mut performer, bytes);
+    }
+}
+
+struct Performer<'a, F: FnMut(Event) + 'a> {
+    state: &'a mut State,
+    style: &'a mut Style,
+    foreground: &'a mut CellColor,
+    background: &'a mut CellColor,
+    underline_color: &'a mut Option<CellColor>,
+    focus_reporting: &'a mut bool,
+    palette: &'a mut Vec<Option<Color>>,
+    cursor_color: &'a mut Option<Color>,
+    initial_colors: &'a mut Option<(Color, Color)>,
+    dcs: &'a mut Option<Vec<u8>>,
+    join: &'a mut bool,
+    callback: &'a mut F,
+}
+
+fn resolve(palette: &[Option<Color>], color: Color) -> Color {
+    match color {
+        Color::Ansi(index) => palette
+            .get(index as usize)
+            .copied()
+            .flatten()
+            .unwrap_or(color),
+        color => color,
+    }
+}
+
+impl<'a, F: FnMut(Event)> Performer<'a, F> {
+    /// Let ransid handle a sequence, attaching the current style and colours to
+    /// every character it prints and every block it clears
+    fn ransid<G: FnOnce(&mut State, &mut dyn FnMut(ransid::Event))>(&mut self, f: G) {
+        let style = *self.style;
+        let foreground = *self.foreground;
+        let background = *self.background;
+        let underline_color = *self.underline_color;
+        let w = self.state.w;
+        let callback = &mut *self.callback;
+        f(self.state, &mut |event| match event {
+            ransid::Event::Char { x, y, c, .. } => callback(Event::Char {
+                x,
+                y,
+                c,
+                color: foreground,
+                style,
+                underline_color,
+                wide: c.width() == Some(2) && x + 1 < w,
+            }),
+            ransid::Event::Rect { x, y, w, h, .. } => callback(Event::Rect {
+                x,
+                y,
+                w,
+                h,
+                color: background,
+            }),
+            event => callback(Event::Ransid(event)),
+        });
+    }
+
+    /// The colour in the slot, as it is drawn
+    fn color(&self, slot: ColorSlot) -> Color {
+        match slot {
+            ColorSlot::Palette(index) => resolve(self.palette, Color::Ansi(index)),
+            ColorSlot::Foreground => resolve(self.palette, self.state.foreground_default),
+            ColorSlot::Background => resolve(self.palette, self.state.background_default),
+            ColorSlot::Cursor => match *self.cursor_color {
+                Some(color) => color,
+                None => self.color(ColorSlot::Foreground),
+            },
+        }
+    }
+
+    /// Change the colour in the slot, or put back the colour it had before
+    /// programs changed it when `color` is `None`
+    fn set_color(&mut self, slot: ColorSlot, color: Option<Color>) {
+        let state = &mut *self.state;
+        let initial = *self
+            .initial_colors
+            .get_or_insert((state.foreground_default, state.background_default));
+
+        match slot {
+            ColorSlot::Palette(index) => {
+                if self.palette.is_empty() {
+                    self.palette.resize(256, None);
+                }
+                self.palette[index as usize] = color;
+            }
+            ColorSlot::Foreground => state.foreground_default = color.unwrap_or(initial.0),
+            ColorSlot::Background => state.background_default = color.unwrap_or(initial.1),
+            ColorSlot::Cursor => *self.cursor_color = color,
+        }
+
+        (self.callback)(Event::Recolor { slot });
+    }
+
+    /// Send a reply to the program
+    fn reply(&mut self, reply: &str) {
+        (self.callback)(Event::Ransid(ransid::Event::Input {
+            data: reply.as_bytes(),
+        }));
+    }
+
+    /// Reply to a query of the colour in the slot, as OSC `number` ; colour
+    fn report_color(&mut self, number: &str, slot: ColorSlot, bell_terminated: bool) {
+        let reply = format!(
+            "\x1B]{};{}{}",
+            number,
+            color_spec(self.color(slot)),
+            if bell_terminated { "\x07" } else { "\x1B\\" }
+        );
+        self.reply(&reply);
+    }
+
+    /// Reply to a DECRQM query of a mode, as `CSI ? mode ; value $ y`, where the
+    /// value is 1 when the mode is set, 2 when it is reset and 0 when it is not
+    /// known
+    fn report_mode(&mut self, mode: u16, private: bool) {
+        let state = &*self.state;
+        let set = match (private, mode) {
+            (true, 6) => Some(state.origin),
+            (true, 7) => Some(state.autowrap),
+            (true, 25) => Some(state.cursor),
+            (true, 1000) => Some(state.mouse_vt200),
+            (true, 1002) => Some(state.mouse_btn),
+            (true, 1004) => Some(*self.focus_reporting),
+            (true, 1006) => Some(state.mouse_sgr),
+            (true, 1015) => Some(state.mouse_rxvt),
+            (true, 47) | (true, 1047) | (true, 1049) => {
+                (self.callback)(Event::AlternateScreenQuery { mode });
+                return;
+            }
+            _ => None,
+        };
+        let value = match set {
+            Some(true) => 1,
+            Some(false) => 2,
+            None => 0,
+        };
+        let reply = format!(
+            "\x1B[{}{};{}$y",
+            if private { "?" } else { "" },
+            mode,
+            value
+        );
+        self.reply(&reply);
+    }
+
+    /// Reply to XTGETTCAP with the capabilities named in hex, one reply each.
+    /// Known ones are given back as `DCS 1 + r name = value ST`, and unknown
+    /// ones as `DCS 0 + r name ST`
+    fn report_capabilities(&mut self, names: &[u8]) {
+        for hex in names.split(|&byte| byte == b';') {
+            let hex = match str::from_utf8(hex) {
+                Ok(hex) if hex.len() % 2 == 0 && hex.bytes().all(|b| b.is_ascii_hexdigit()) => hex,
+                _ => continue,
+            };
+            let name = decode_hex(hex).unwrap_or_default();
+            let found = CAPABILITIES
+                .iter()
+                .find(|&&(capability, _)| capability.as_bytes() == &name[..]);
+
+            let reply = match found {
+                Some(&(_, Some(value))) => {
+                    let value: String = value.bytes().map(|b| format!("{:02X}", b)).collect();
+                    format!("\x1BP1+r{}={}\x1B\\", hex, value)
+                }
+                Some(&(_, None)) => format!("\x1BP1+r{}\x1B\\", hex),
+                None => format!("\x1BP0+r{}\x1B\\", hex),
+            };
+            self.reply(&reply);
+        }
+    }
+
+    /// Query, change or reset colours with OSC 4, 10 to 12, 104 and 110 to 112
+    fn color_osc(&mut self, number: u16, params: &[&[u8]], bell_terminated: bool) {
+        let params: Vec<&str> = params
+            .iter()
+            .map(|param| str::from_utf8(param).unwrap_or(""))
+            .collect();
+
+        match number {
+            // Pairs of an index and a colour, or ? to query the colour
+            4 => {
+                for pair in params.chunks(2) {
+                    let index = match pair[0].parse() {
+                        Ok(index) => index,
+                        Err(_) => return,
+                    };
+                    match pair.get(1) {
+                        Some(&"?") => {
+                            let number = format!("4;{}", index);
+                            self.report_color(&number, ColorSlot::Palette(index), bell_terminated);
+                        }
+                        Some(spec) => {
+                            if let Some(color) = parse_color(spec) {
+                                self.set_color(ColorSlot::Palette(index), Some(color));
+                            }
+                        }
+                        None => (),
+                    }
+                }
+            }
+            // Colours after the first are for the slots that follow it
+            10..=12 => {
+                let slots = [
+                    ColorSlot::Foreground,
+                    ColorSlot::Background,
+                    ColorSlot::Cursor,
+                ];
+                let first = number as usize - 10;
+                for (offset, spec) in params.iter().enumerate() {
+                    let slot = match slots.get(first + offset) {
+                        Some(&slot) => slot,
+                        None => break,
+                    };
+                    if *spec == "?" {
+                        let number = (first + offset + 10).to_string();
+                        self.report_color(&number, slot, bell_terminated);
+                    } else if let Some(color) = parse_color(spec) {
+                        self.set_color(slot, Some(color));
+                    }
+                }
+            }
+            // Without any indices, every colour of the palette is reset
+            104 if params.iter().all(|param| param.is_empty()) => {
+                for index in 0..self.palette.len() {
+                    if self.palette[index].is_some() {
+                        self.set_color(ColorSlot::Palette(index as u8), None);
+                    }
+                }
+            }
+            104 => {
+                for index in params.iter().filter_map(|param| param.parse().ok()) {
+                    self.set_color(ColorSlot::Palette(index), None);
+                }
+            }
+            110 => self.set_color(ColorSlot::Foreground, None),
+            111 => self.set_color(ColorSlot::Background, None),
+            112 => self.set_color(ColorSlot::Cursor, None),
+            _ => (),
+        }
+    }
+
+    /// Add a character to the grapheme cluster that was printed last
+    fn combine(&mut self, c: char) {
+        // There is nothing to combine with at the start of a row
+        if self.state.x == 0 {
+            *self.join = false;
+            return;
+        }
+
+        *self.join = c == '\u{200D}';
+        (self.callback)(Event::Combine {
+            x: self.state.x - 1,
+            y: self.state.y,
+            c,
+        });
+    }
+
+    /// Print a character that takes up two blocks
+    fn print_wide(&mut self, c: char) {
+        // Rather than being split over two rows, wide characters wrap early
+        if self.state.autowrap && self.state.x + 1 == self.state.w {
+            self.state.x = self.state.w;
+        }
+
+        self.ransid(|state, mut callback| state.print(c, &mut callback));
+
+        // Without autowrap, a wide character in the last column is cut in half
+        if self.state.x < self.state.w {
+            (self.callback)(Event::Spacer {
+                x: self.state.x,
+                y: self.state.y,
+            });
+            self.state.x += 1;
+        }
+    }
+
+    /// Select graphic rendition
+    fn sgr(&mut self, params: &Params) {
+        let foreground = &mut *self.foreground;
+        let background = &mut *self.background;
+        let style = &mut *self.style;
+        let underline_color = &mut *self.underline_color;
+
+        let mut iter = params.iter();
+        while let Some(param) = iter.next() {
+            match param[0] {
+                0 => {
+                    *foreground = CellColor::Default;
+                    *background = CellColor::Default;
+                    *style = Style::default();
+                    *underline_color = None;
+                }
+                1 => style.bold = true,
+                2 => style.dim = true,
+                3 => style.italic = true,
+                4 => {
+                    style.underline = match param.get(1) {
+                        Some(0) => Underline::None,
+                        Some(2) => Underline::Double,
+                        Some(3) => Underline::Curly,
+                        Some(4) => Underline::Dotted,
+                        Some(5) => Underline::Dashed,
+                        _ => Underline::Single,
+                    }
+                }
+                5 | 6 => style.blink = true,
+                7 => style.inverse = true,
+                8 => style.hidden = true,
+                9 => style.strikethrough = true,
+                21 => style.underline = Underline::Double,
+                22 => {
+                    style.bold = false;
+                    style.dim = false;
+                }
+                23 => style.italic = false,
+                24 => style.underline = Underline::None,
+                25 => style.blink = false,
+                27 => style.inverse = false,
+                28 => style.hidden = false,
+                29 => style.strikethrough = false,
+                value @ 30..=37 => *foreground = CellColor::Palette(value as u8 - 30),
+                38 => {
+                    if let Some(color) = extended_color(param, &mut iter) {
+                        *foreground = color;
+                    }
+                }
+                39 => *foreground = CellColor::Default,
+                value @ 40..=47 => *background = CellColor::Palette(value as u8 - 40),
+                48 => {
+                    if let Some(color) = extended_color(param, &mut iter) {
+                        *background = color;
+                    }
+                }
+                49 => *background = CellColor::Default,
+                58 => {
+                    if let Some(color) = extended_color(param, &mut iter) {
+                        *underline_color = Some(color);
+                    }
+                }
+                59 => *underline_color = None,
+                value @ 90..=97 => *foreground = CellColor::Palette(value as u8 - 90 + 8),
+                value @ 100..=107 => *background = CellColor::Palette(value as u8 - 100 + 8),
+                _ => (),
+            }
+        }
+    }
+}
+
+/// Read the colour of a `38`, `48` or `58` attribute, which is either given as
+/// sub-parameters (`38:2::r:g:b`) or in the parameters that follow (`38;2;r;g;b`)
+fn extended_color(param: &[u16], iter: &mut ParamsIter) -> Option<CellColor> {
+    if param.len() > 1 {
+        return match param[1] {
+            2 => {
+                // The colour space identifier before the components is optional
+                let rgb = &param[2..];
+                let rgb = if rgb.len() > 3 { &rgb[1..] } else { rgb };
+                match *rgb {
+                    [r, g, b, ..] => Some(CellColor::Rgb(r as u8, g as u8, b as u8)),
+                    _ => None,
+                }
+            }
+            5 => param.get(2).map(|&value| CellColor::Palette(value as u8)),
+            _ => None,
+        };
+    }
+
+    let mut next = || iter.next().map(|param| param[0]).unwrap_or(0);
+    match next() {
+        2 => {
+            let r = next();
+            let g = next();
+            let b = next();
+            Some(CellColor::Rgb(r as u8, g as u8, b as u8))
+        }
+        5 => Some(CellColor::Palette(next() as u8)),
+        _ => None,
+    }
+}
+
+/// The path of a `file://host/path` URL, when the host is this machine. Shells
+/// on other machines, as over ssh, report paths that are not on this one
+fn cwd_path(url: &[u8]) -> Option<PathBuf> {
+    let rest = url.strip_prefix(b"file://")?;
+    let start = rest.iter().position(|&byte| byte == b'/')?;
+    let host = str::from_utf8(&rest[..start]).ok()?;
+    let local = host.is_empty()
+        || host.eq_ignore_ascii_case("localhost")
+        || hostname().is_some_and(|hostname| host.eq_ignore_ascii_case(&hostname));
+    if !local {
+        return None;
+    }
+
+    let mut path = Vec::with_capacity(rest.len() - start);
+    let mut bytes = rest[start..].iter();
+    while let Some(&byte) = bytes.next() {
+        if byte == b'%' {
+            let hex = bytes.as_slice().get(..2);
+            if let Some(value) = hex
+                .and_then(|hex| str::from_utf8(hex).ok())
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            {
+                path.push(value);
+                bytes.nth(1);
+                continue;
+            }
+        }
+        path.push(byte);
+    }
+    Some(PathBuf::from(OsStr::from_bytes(&path)))
+}
+
+/// The name of this machine
+#[cfg(not(target_os = "redox"))]
+fn hostname() -> Option<String> {
+    let mut name = [0u8; 256];
+    if unsafe { libc::gethostname(name.as_mut_ptr() as *mut libc::c_char, name.len()) } != 0 {
+        return None;
+    }
+    let len = name.iter().position(|&byte| byte == 0)?;
+    str::from_utf8(&name[..len]).ok().map(String::from)
+}
+
+/// The name of this machine
+#[cfg(target_os = "redox")]
+fn hostname() -> Option<String> {
+    fs::read_to_string("/etc/hostname")
+        .ok()
+        .map(|hostname| hostname.trim().to_string())
+}
+
+impl<'a, F: FnMut(Event)> Perform for Performer<'a, F> {
+    fn print(&mut self, c: char) {
+        if *self.join || c.width() == Some(0) {
+            self.combine(c);
+        } else if c.width() == Some(2) {
+            self.print_wide(c);
+        } else {
+            self.ransid(|state, mut callback| state.print(c, &mut callback));
+        }
+    }
+
+    fn execute(&mut self, byte: u8) {
+        *self.join = false;
+        self.ransid(|state, mut callback| state.execute(byte as char, &mut callback));
+    }
+
+    fn osc_dispatch(&mut self, params: &[&[u8]], bell_terminated: bool) {
+        let number = params
+            .first()
+            .and_then(|number| str::from_utf8(number).ok())
+            .and_then(|number| number.parse().ok());
+        if let Some(number @ (4 | 10..=12 | 104 | 110..=112)) = number {
+            self.color_osc(number, &params[1..], bell_terminated);
+            return;
+        }
+
+        if params.first() == Some(&&b"8"[..]) {
+            // Hyperlink, as OSC 8 ; params ; URI, where the URI may itself contain
+            // semicolons. An empty URI ends the link
+            let uri = params.get(2..).unwrap_or(&[]).join(&b';');
+            let uri = if uri.is_empty() {
+                None
+            } else {
+                Some(String::from_utf8_lossy(&uri).into_owned())
+            };
+            (self.callback)(Event::Link { uri });
+            return;
+        }
+
+        if params.first() == Some(&&b"7"[..]) {
+            // Working directory, as OSC 7 ; file://host/path, where the path may
+            // contain semicolons and is percent-encoded
+            let url = params.get(1..).unwrap_or(&[]).join(&b';');
+            if let Some(path) = cwd_path(&url) {
+                (self.callback)(Event::Cwd { path });
+            }
+            return;
+        }
+
+        if params.first() == Some(&&b"133"[..]) {
+            // Semantic prompts, as OSC 133 ; A, B, C or D, where D may be
+            // followed by ; and the exit status of the command
+            let mark = match params.get(1).and_then(|param| param.first()) {
+                Some(b'A') => PromptMark::Prompt,
+                Some(b'B') => PromptMark::Command,
+                Some(b'C') => PromptMark::Output,
+                Some(b'D') => PromptMark::Finished(
+                    params
+                        .get(2)
+                        .and_then(|status| str::from_utf8(status).ok())
+                        .and_then(|status| status.parse().ok()),
+                ),
+                _ => return,
+            };
+            let y = self.state.y;
+            (self.callback)(Event::Mark { y, mark });
+            return;
+        }
+
+        self.ransid(|state, mut callback| state.osc(params, &mut callback));
+    }
+
+    fn csi_dispatch(&mut self, params: &Params, intermediates: &[u8], ignore: bool, c: char) {
+        *self.join = false;
+        if ignore {
+            return;
+        }
+
+        let first = params.iter().next().map_or(0, |param| param[0]);
+
+        if c == 'c' {
+            // Primary device attributes, as a VT220 with colours, and secondary
+            // device attributes with the version as major * 10000 + minor * 100
+            // + patch. Tertiary ones are not answered
+            if first != 0 {
+                return;
+            }
+            match intermediates {
+                b"" => self.reply("\x1B[?62;22c"),
+                b">" => {
+                    let part = |part: &str| part.parse::<u32>().unwrap_or(0);
+                    let version = part(env!("CARGO_PKG_VERSION_MAJOR")) * 10000
+                        + part(env!("CARGO_PKG_VERSION_MINOR")) * 100
+                        + part(env!("CARGO_PKG_VERSION_PATCH"));
+                    self.reply(&format!("\x1B[>1;{};0c", version));
+                }
+                _ => (),
+            }
+            return;
+        }
+
+        if c == 'q' && intermediates == b">" {
+            // XTVERSION, the name and version of the terminal
+            if first == 0 {
+                let reply = format!("\x1BP>|orbterm {}\x1B\\", env!("CARGO_PKG_VERSION"));
+                self.reply(&reply);
+            }
+            return;
+        }
+
+        if c == 'n' && intermediates.is_empty() && first == 5 {
+            // Device status report (DSR), which is always fine. Cursor position
+            // reports are left to ransid
+            self.reply("\x1B[0n");
+            return;
+        }
+
+        if c == 'p' && (intermediates == b"$" || intermediates == b"?$") {
+            // Request mode (DECRQM)
+            self.report_mode(first, intermediates == b"?$");
+            return;
+        }
+
+        if c == 'q' && intermediates == b" " {
+            // Set cursor style (DECSCUSR), where odd values blink
+            let value = params.iter().next().map_or(0, |param| param[0]);
+            let shape = match value {
+                0 => None,
+                1 | 2 => Some(CursorShape::Block),
+                3 | 4 => Some(CursorShape::Underline),
+                5 | 6 => Some(CursorShape::Bar),
+                _ => return,
+            };
+            (self.callback)(Event::CursorShape {
+                shape,
+                blink: value % 2 == 1,
+            });
+            return;
+        }
+
+        if c == 't' && intermediates.is_empty() {
+            // Window operations, of which reporting the size of the grid in pixels,
+            // of a block and in blocks with CSI 14 t, CSI 16 t and CSI 18 t, and
+            // saving and restoring the title with CSI 22 ; 0 t and CSI 23 ; 0 t
+            // are supported. 1 is the icon title, which is not shown anywhere
+            let mut params = params.iter().map(|param| param[0]);
+            let push = match params.next() {
+                Some(14) => {
+                    (self.callback)(Event::SizeQuery { block: false });
+                    return;
+                }
+                Some(16) => {
+                    (self.callback)(Event::SizeQuery { block: true });
+                    return;
+                }
+                Some(18) => {
+                    let reply = format!("\x1B[8;{};{}t", self.state.h, self.state.w);
+                    self.reply(&reply);
+                    return;
+                }
+                Some(22) => true,
+                Some(23) => false,
+                _ => return,
+            };
+            if params.next().unwrap_or(0) != 1 {
+                (self.callback)(Event::TitleStack { push });
+            }
+            return;
+        }
+
+        if c == 'm' {
+            // Sequences such as CSI > 4 m share the final byte, but are not SGR
+            if intermediates.is_empty() {
+                self.sgr(params);
+            }
+            return;
+        }
+
+        if c == 'h' || c == 'l' {
+            // Private modes ransid does not know about are picked out, and the rest
+            // are left to ransid one at a time, as it only looks at the first
+            for param in params.iter() {
+                let mode = i64::from(param[0]);
+                if mode == 1004 && intermediates == b"?" {
+                    *self.focus_reporting = c == 'h';
+                } else {
+                    self.ransid(|state, mut callback| {
+                        state.csi(c, &[mode], intermediates, &mut callback)
+                    });
+                }
+            }
+            return;
+        }
+
+        // A sequence without parameters is reported as a single zero, while ransid
+        // expects no parameters at all so that it picks the right default
+        let params: Vec<i64> = if params.len() == 1 && params.iter().all(|param| param == [0]) {
+            Vec::new()
+        } else {
+            params.iter().map(|param| i64::from(param[0])).collect()
+        };
+
+        self.ransid(|state, mut callback| state.csi(c, &params, intermediates, &mut callback));
+    }
+
+    fn hook(&mut self, _params: &Params, intermediates: &[u8], ignore: bool, c: char) {
+        *self.join = false;
+        // Only XTGETTCAP is answered, other device control strings are dropped
+        *self.dcs = if !ignore && intermediates == b"+" && c == 'q' {
+            Some(Vec::new())
+        } else {
+            None
+        };
+    }
+
+    fn put(&mut self, byte: u8) {
+        if let Some(ref mut dcs) = *self.dcs {
+            if dcs.len() < MAX_DCS_LEN {
+                dcs.push(byte);
+            }
+        }
+    }
+
+    fn unhook(&mut self) {
+        if let Some(names) = self.dcs.take() {
+            self.report_capabilities(&names);
+        }
+    }
+
+    fn esc_dispatch(&mut self, intermediates: &[u8], ignore: bool, byte: u8) {
+        *self.join = false;
+        if ignore {
+            return;
+        }
+
+        if byte == b'c' && intermediates.is_empty() {
+            // Full reset
+            *self.style = Style::default();
+            *self.foreground = CellColor::Default;
+            *self.background = CellColor::Default;
+            *self.underline_color = None;
+            *self.focus_reporting = false;
+            (self.callback)(Event::Link { uri: None });
+            (self.callback)(Event::CursorShape {
+                shape: None,
+                blink: false,
+            });
+        }
+
+        self.ransid(|state, mut callback| {
+            state.esc(byte as char, &[], intermediates, &mut callback)
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state() -> State {
+        ransid::Console::new(80, 24).state
+    }
+
+    /// Everything the parser replied to the program with
+    fn replies(parser: &mut Parser, state: &mut State, bytes: &[u8]) -> String {
+        let mut replies = String::new();
+        parser.advance(state, bytes, |event| {
+            if let Event::Ransid(ransid::Event::Input { data }) = event {
+                replies.push_str(str::from_utf8(data).unwrap());
+            }
+        });
+        replies
+    }
+
+    #[test]
+    fn extended_colors() {
+        let (mut parser, mut state) = (Parser::new(), state());
+
+        // Sub-parameters, with and without the colour space identifier
+        parser.advance(&mut state, b"\x1B[38:2::1:2:3;48:2:4:5:6;58:5:7m", |_| ());
+        assert_eq!(parser.foreground, CellColor::Rgb(1, 2, 3));
+        assert_eq!(parser.background, CellColor::Rgb(4, 5, 6));
+        assert_eq!(parser.underline_color, Some(CellColor::Palette(7)));
+
+        // Parameters, after which the next attribute still applies
+        parser.advance(&mut state, b"\x1B[38;5;200;48;2;7;8;9;1m", |_| ());
+        assert_eq!(parser.foreground, CellColor::Palette(200));
+        assert_eq!(parser.background, CellColor::Rgb(7, 8, 9));
+        assert!(parser.style.bold);
+    }
+
+    #[test]
+    fn sgr() {
+        let (mut parser, mut state) = (Parser::new(), state());
+
+        parser.advance(&mut state, b"\x1B[1;2;3;4:3;5;7;8;9;31;102m", |_| ());
+        let expected = Style {
+            bold: true,
+            dim: true,
+            italic: true,
+            underline: Underline::Curly,
+            blink: true,
+            inverse: true,
+            hidden: true,
+            strikethrough: true,
+        };
+        assert_eq!(parser.style, expected);
+        assert_eq!(parser.foreground, CellColor::Palette(1));
+        assert_eq!(parser.background, CellColor::Palette(10));
+
+        parser.advance(&mut state, b"\x1B[22;23;24;25;27;28;29;39;49m", |_| ());
+        assert_eq!(parser.style, Style::default());
+        assert_eq!(parser.foreground, CellColor::Default);
+        assert_eq!(parser.background, CellColor::Default);
+
+        parser.advance(&mut state, b"\x1B[21m", |_| ());
+        assert_eq!(parser.style.underline, Underline::Double);
+        parser.advance(&mut state, b"\x1B[4:0m", |_| ());
+        assert_eq!(parser.style.underline, Underline::None);
+
+        parser.advance(&mut state, b"\x1B[4;95;58;5;1m\x1B[0m", |_| ());
+        assert_eq!(parser.style, Style::default());
+        assert_eq!(parser.foreground, CellColor::Default);
+        assert_eq!(parser.underline_color, None);
+    }
+
+    #[test]
+    fn cursor_shapes() {
+        let (mut parser, mut state) = (Parser::new(), state());
+        let mut shapes = Vec::new();
+        parser.advance(
+            &mut state,
+            b"\x1B[0 q\x1B[1 q\x1B[4 q\x1B[6 q\x1B[7 q",
+            |event| {
+                if let Event::CursorShape { shape, blink } = event {
+                    shapes.push((shape, blink));
+                }
+            },
+        );
+        assert_eq!(
+            shapes,
+            [
+                (None, false),
+                (Some(CursorShape::Block), true),
+                (Some(CursorShape::Underline), false),
+                (Some(CursorShape::Bar), false),
+            ]
+        );
+    }
+
+    #[test]
+    fn hyperlinks() {
+        let (mut parser, mut state) = (Parser::new(), state());
+        let mut events = Vec::new();
+        parser.advance(
+            &mut state,
+            b"\x1B]8;id=1;http://a/;b\x07x\x1B]8;;\x1B\\",
+            |event| match event {
+                Event::Link { uri } => events.push(format!("link {:?}", uri)),
+                Event::Char { c, .. } => events.push(format!("char {}", c)),
+                _ => (),
+            },
+        );
+        assert_eq!(
+            events,
+            ["link Some(\"http://a/;b\")", "char x", "link None"]
+        );
+    }
+
+    #[test]
+    fn prompt_marks() {
+        let (mut parser, mut state) = (Parser::new(), state());
+        let mut marks = Vec::new();
+        parser.advance(
+            &mut state,
+            b"\x1B]133;A\x07\x1B]133;B\x07\x1B]133;C\x07\x1B]133;D;1\x07\x1B]133;D\x07",
+            |event| {
+                if let Event::Mark { mark, .. } = event {
+                    marks.push(format!("{:?}", mark));
+                }
+            },
+        );
+        assert_eq!(
+            marks,
+            [
+                "Prompt",
+                "Command",
+                "Output",
+                "Finished(Some(1))",
+                "Finished(None)"
+            ]
+        );
+    }
+
+    #[test]
+    fn working_directory() {
+        let (mut parser, mut state) = (Parser::new(), state());
+        let mut events = Vec::new();
+        parser.advance(
+            &mut state,
+            b"\x1B]7;file:///tmp\x07\x1B]2;hi\x07",
+            |event| match event {
+                Event::Cwd { path } => events.push(format!("cwd {}", path.display())),
+                Event::Ransid(ransid::Event::Title { title }) => {
+                    events.push(format!("title {}", title))
+                }
+                _ => (),
+            },
+        );
+        assert_eq!(events, ["cwd /tmp", "title hi"]);
+    }
+
+    #[test]
+    fn cwd_paths() {
+        let path = |url: &str| cwd_path(url.as_bytes());
+        assert_eq!(
+            path("file:///home/a%20b;c"),
+            Some(PathBuf::from("/home/a b;c"))
+        );
+        assert_eq!(
+            path("file://localhost/tmp/%zz"),
+            Some(PathBuf::from("/tmp/%zz"))
+        );
+        assert_eq!(path("file://elsewhere.invalid/tmp"), None);
+        assert_eq!(path("http://localhost/tmp"), None);
+        assert_eq!(path("file://localhost"), None);
+
+        let hostname = hostname().unwrap();
+        let url = format!("file://{}/tmp", hostname);
+        assert_eq!(path(&url), Some(PathBuf::from("/tmp")));
+    }
+
+    #[test]
+    fn device_status() {
+        let (mut parser, mut state) = (Parser::new(), state());
+        assert_eq!(replies(&mut parser, &mut state, b"\x1B[5n"), "\x1B[0n");
+        assert_eq!(replies(&mut parser, &mut state, b"\x1B[6n"), "\x1B[1;1R");
+    }
+
+    #[test]
+    fn report_mode() {
+        let (mut parser, mut state) = (Parser::new(), state());
+        assert_eq!(
+            replies(&mut parser, &mut state, b"\x1B[?25$p"),
+            "\x1B[?25;1$y"
+        );
+        assert_eq!(
+            replies(&mut parser, &mut state, b"\x1B[?25l\x1B[?25$p"),
+            "\x1B[?25;2$y"
+        );
+        assert_eq!(
+            replies(&mut parser, &mut state, b"\x1B[?1004h\x1B[?1004$p"),
+            "\x1B[?1004;1$y"
+        );
+        assert_eq!(
+            replies(&mut parser, &mut state, b"\x1B[?5555$p"),
+            "\x1B[?5555;0$y"
+        );
+        assert_eq!(replies(&mut parser, &mut state, b"\x1B[4$p"), "\x1B[4;0$y");
+
+        let mut query = None;
+        parser.advance(&mut state, b"\x1B[?1049$p", |event| {
+            if let Event::AlternateScreenQuery { mode } = event {
+                query = Some(mode);
+            }
+        });
+        assert_eq!(query, Some(1049));
+    }
+
+    #[test]
+    fn report_capabilities() {
+        let (mut parser, mut state) = (Parser::new(), state());
+        // TN, colors, Su and an unknown x, with one that is not valid hex
+        let reply = replies(
+            &mut parser,
+            &mut state,
+            b"\x1BP+q544e;636f6c6f7273;5375;78;zz\x1B\\",
+        );
+        assert_eq!(
+            reply,
+            "\x1BP1+r544e=6F72627465726D\x1B\\\
+             \x1BP1+r636f6c6f7273=323536\x1B\\\
+             \x1BP1+r5375\x1B\\\
+             \x1BP0+r78\x1B\\"
+        );
+
+        // Other device control strings are not answered
+        assert_eq!(replies(&mut parser, &mut state, b"\x1BP$q544e\x1B\\"), "");
+    }
+}
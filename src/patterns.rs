@@ -0,0 +1,209 @@
+//! Text on the grid that can be opened or copied, like URLs and `file:line:col`
+//! references, found with regexes
+
+use std::path::Path;
+
+use regex::Regex;
+
+use config::{HintPattern, OpenPattern};
+
+/// URLs, `file:line` or `file:line:col` references and absolute paths, with the
+/// arguments the opener is given for them. Most openers can't go to a line, so
+/// they are only given the file of a `file:line` reference
+pub const DEFAULT_PATTERNS: [(&str, &str); 3] = [(URL, "$0"), (FILE_LINE, "${file}"), (PATH, "$0")];
+
+/// Editors that go to the line given as `+line` before the file, which they
+/// are given for `file:line` references instead of only the file
+const LINE_EDITORS: [&str; 7] = ["vi", "vim", "nvim", "nano", "emacs", "emacsclient", "micro"];
+const EDITOR_FILE_LINE: &str = "+${line} ${file}";
+
+/// URLs are opened, git SHAs, IP addresses, paths and numbers of three digits
+/// or more are copied
+pub const DEFAULT_HINT_PATTERNS: [(&str, HintAction); 6] = [
+    (URL, HintAction::Open),
+    (r"\b[0-9a-f]{7,40}\b", HintAction::Copy),
+    (r"\b\d{1,3}(?:\.\d{1,3}){3}(?::\d+)?\b", HintAction::Copy),
+    (FILE_LINE, HintAction::Copy),
+    (PATH, HintAction::Copy),
+    (r"\b\d{3,}\b", HintAction::Copy),
+];
+
+const URL: &str = r#"\b[a-z][a-z0-9+.-]*://[^\s<>"'`]*[^\s<>"'`.,:;!?)\]}]"#;
+const FILE_LINE: &str = r"(?P<file>(?:[\w.~-]*/)*[\w.-]*\w\.\w+):(?P<line>\d+)(?::\d+)?";
+const PATH: &str = r"(?:~|\B)(?:/[\w.~+-]+)+/?";
+
+/// What happens to a match when its hint is typed
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HintAction {
+    Copy,
+    Open,
+}
+
+pub struct Pattern {
+    regex: Regex,
+    command: String,
+    action: HintAction,
+}
+
+/// A match on one row of the grid, covering the columns from `start` up to
+/// but not including `end`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Match {
+    pub y: usize,
+    pub start: usize,
+    pub end: usize,
+    pub text: String,
+    pub action: HintAction,
+    /// The command that opens the match, with its arguments
+    pub args: Vec<String>,
+}
+
+impl Pattern {
+    /// Compile the configured patterns for Ctrl-click, falling back to the
+    /// default ones opened with `opener`. Patterns that do not compile are left
+    /// out
+    pub fn load(patterns: &Option<Vec<OpenPattern>>, opener: &str) -> Vec<Pattern> {
+        match *patterns {
+            Some(ref patterns) => patterns
+                .iter()
+                .filter_map(|pattern| {
+                    Pattern::new(&pattern.regex, &pattern.command, HintAction::Open, opener)
+                })
+                .collect(),
+            None => DEFAULT_PATTERNS
+                .iter()
+                .filter_map(|&(regex, arg)| {
+                    let arg = if regex == FILE_LINE && goes_to_lines(opener) {
+                        EDITOR_FILE_LINE
+                    } else {
+                        arg
+                    };
+                    let command = Some(format!("{} {}", opener, arg));
+                    Pattern::new(regex, &command, HintAction::Open, opener)
+                })
+                .collect(),
+        }
+    }
+
+    /// Compile the configured patterns for hints, falling back to the default
+    /// ones
+    pub fn load_hints(patterns: &Option<Vec<HintPattern>>, opener: &str) -> Vec<Pattern> {
+        match *patterns {
+            Some(ref patterns) => patterns
+                .iter()
+                .filter_map(|pattern| {
+                    let action = pattern.action.unwrap_or(HintAction::Copy);
+                    Pattern::new(&pattern.regex, &pattern.command, action, opener)
+                })
+                .collect(),
+            None => DEFAULT_HINT_PATTERNS
+                .iter()
+                .filter_map(|&(regex, action)| Pattern::new(regex, &None, action, opener))
+                .collect(),
+        }
+    }
+
+    fn new(
+        regex: &str,
+        command: &Option<String>,
+        action: HintAction,
+        opener: &str,
+    ) -> Option<Pattern> {
+        match Regex::new(regex) {
+            Ok(compiled) => Some(Pattern {
+                regex: compiled,
+                command: command.clone().unwrap_or_else(|| opener.to_string()),
+                action,
+            }),
+            Err(err) => {
+                eprintln!("orbterm: invalid pattern '{}': {}", regex, err);
+                None
+            }
+        }
+    }
+
+    /// The match covering column `x` of the row, if any. `columns` holds the
+    /// column of every byte of `text`, and the width of the row at its end
+    pub fn find(&self, text: &str, columns: &[usize], y: usize, x: usize) -> Option<Match> {
+        self.find_all(text, columns, y)
+            .find(|found| found.start <= x && x < found.end)
+    }
+
+    /// Every match in the row
+    pub fn find_all<'a>(
+        &'a self,
+        text: &'a str,
+        columns: &'a [usize],
+        y: usize,
+    ) -> impl Iterator<Item = Match> + 'a {
+        self.regex.captures_iter(text).map(move |captures| {
+            let whole = captures.get(0).unwrap();
+            Match {
+                y,
+                start: columns[whole.start()],
+                end: columns[whole.end()],
+                text: whole.as_str().to_string(),
+                action: self.action,
+                args: self.args(&captures),
+            }
+        })
+    }
+
+    /// Words of the command with `$1` or `${name}` in them are given the
+    /// captured groups. A command without any is given the whole match as its
+    /// last argument
+    fn args(&self, captures: &regex::Captures) -> Vec<String> {
+        let mut args = Vec::new();
+        for word in self.command.split_whitespace() {
+            let mut arg = String::new();
+            captures.expand(word, &mut arg);
+            args.push(arg);
+        }
+
+        if !self.command.contains('$') {
+            args.push(captures[0].to_string());
+        }
+        args
+    }
+}
+
+/// Whether the opener is an editor that is given the line of references
+fn goes_to_lines(opener: &str) -> bool {
+    opener
+        .split_whitespace()
+        .next()
+        .and_then(|program| Path::new(program).file_name())
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| LINE_EDITORS.contains(&name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(opener: &str, text: &str) -> Vec<String> {
+        let columns: Vec<usize> = (0..=text.len()).collect();
+        Pattern::load(&None, opener)
+            .iter()
+            .find_map(|pattern| pattern.find(text, &columns, 0, 0))
+            .unwrap()
+            .args
+    }
+
+    #[test]
+    fn file_line_references() {
+        assert_eq!(
+            args("xdg-open", "src/main.rs:12:3"),
+            ["xdg-open", "src/main.rs"]
+        );
+        assert_eq!(
+            args("/usr/bin/vim", "src/main.rs:12:3"),
+            ["/usr/bin/vim", "+12", "src/main.rs"]
+        );
+        assert_eq!(
+            args("emacsclient -n", "main.rs:7"),
+            ["emacsclient", "-n", "+7", "main.rs"]
+        );
+    }
+}
@@ -0,0 +1,115 @@
+//! Everything the console draws into. This is normally a window, but the
+//! console can also render offscreen, for example when benchmarking
+
+use std::cell::Cell;
+
+use orbclient::{Color, Mode, Renderer, Window};
+
+pub trait RenderTarget: Renderer {
+    fn set_size(&mut self, width: u32, height: u32);
+
+    fn set_title(&mut self, title: &str);
+
+    fn clipboard(&self) -> String;
+
+    fn set_clipboard(&mut self, text: &str);
+}
+
+impl RenderTarget for Window {
+    fn set_size(&mut self, width: u32, height: u32) {
+        Window::set_size(self, width, height);
+    }
+
+    fn set_title(&mut self, title: &str) {
+        Window::set_title(self, title);
+    }
+
+    fn clipboard(&self) -> String {
+        Window::clipboard(self)
+    }
+
+    fn set_clipboard(&mut self, text: &str) {
+        Window::set_clipboard(self, text);
+    }
+}
+
+/// A render target that only lives in memory
+pub struct Offscreen {
+    width: u32,
+    height: u32,
+    data: Vec<Color>,
+    mode: Cell<Mode>,
+    title: String,
+    clipboard: String,
+}
+
+impl Offscreen {
+    pub fn new(width: u32, height: u32) -> Self {
+        Offscreen {
+            width,
+            height,
+            data: vec![Color { data: 0 }; width as usize * height as usize],
+            mode: Cell::new(Mode::Blend),
+            title: String::new(),
+            clipboard: String::new(),
+        }
+    }
+
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+}
+
+impl Renderer for Offscreen {
+    fn width(&self) -> u32 {
+        self.width
+    }
+
+    fn height(&self) -> u32 {
+        self.height
+    }
+
+    fn data(&self) -> &[Color] {
+        &self.data
+    }
+
+    fn data_mut(&mut self) -> &mut [Color] {
+        &mut self.data
+    }
+
+    fn sync(&mut self) -> bool {
+        true
+    }
+
+    fn update(&mut self) -> bool {
+        true
+    }
+
+    fn update_rects(&mut self, _rects: &[(i32, i32, u32, u32)]) -> bool {
+        true
+    }
+
+    fn mode(&self) -> &Cell<Mode> {
+        &self.mode
+    }
+}
+
+impl RenderTarget for Offscreen {
+    fn set_size(&mut self, width: u32, height: u32) {
+        self.width = width;
+        self.height = height;
+        self.data = vec![Color { data: 0 }; width as usize * height as usize];
+    }
+
+    fn set_title(&mut self, title: &str) {
+        self.title = title.to_string();
+    }
+
+    fn clipboard(&self) -> String {
+        self.clipboard.clone()
+    }
+
+    fn set_clipboard(&mut self, text: &str) {
+        self.clipboard = text.to_string();
+    }
+}
@@ -0,0 +1,73 @@
+//! Finding text in the grid and the scrollback
+
+use regex::{self, Regex, RegexBuilder};
+
+/// A match, covering the columns from `start` up to but not including `end` of
+/// a line, counted from the oldest line of the scrollback
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub struct SearchMatch {
+    pub line: usize,
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Default)]
+pub struct Search {
+    pub query: String,
+    /// Whether the query is a regex rather than plain text
+    pub regex: bool,
+    pub case_sensitive: bool,
+    /// Every match, in the order they appear in
+    pub matches: Vec<SearchMatch>,
+    /// The index of the match that was jumped to last
+    pub current: Option<usize>,
+    /// Whether the search bar is open and keys edit the query. Copy mode keeps
+    /// the matches once it is closed, to move between them with n and N
+    pub typing: bool,
+}
+
+impl Search {
+    /// The query as a regex, or nothing when it is empty or not a valid regex
+    pub fn compile(&self) -> Option<Regex> {
+        if self.query.is_empty() {
+            return None;
+        }
+
+        let pattern = if self.regex {
+            self.query.clone()
+        } else {
+            regex::escape(&self.query)
+        };
+        RegexBuilder::new(&pattern)
+            .case_insensitive(!self.case_sensitive)
+            .build()
+            .ok()
+    }
+
+    /// The line the search bar shows, with a count of the matches and which of
+    /// the options are on
+    pub fn bar(&self) -> String {
+        let position = match self.current {
+            Some(current) => format!("{}/{}", current + 1, self.matches.len()),
+            None => format!("0/{}", self.matches.len()),
+        };
+        format!(
+            "Find: {}  [{}] regex  [{}] case  {}",
+            self.query,
+            if self.regex { 'x' } else { ' ' },
+            if self.case_sensitive { 'x' } else { ' ' },
+            position
+        )
+    }
+
+    /// Whether the column of the line is part of a match, and of the current one
+    pub fn highlight(&self, line: usize, x: usize) -> Option<bool> {
+        let first = self.matches.partition_point(|found| found.line < line);
+        self.matches[first..]
+            .iter()
+            .enumerate()
+            .take_while(|(_, found)| found.line == line)
+            .find(|(_, found)| found.start <= x && x < found.end)
+            .map(|(index, _)| self.current == Some(first + index))
+    }
+}
@@ -0,0 +1,75 @@
+//! Copy mode, where a cursor is moved over the grid and the scrollback with vi
+//! motions to select text without the mouse
+
+use std::cmp;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ViSelection {
+    /// Every character from the start to the end, started with `v`
+    Char,
+    /// Whole lines, started with `V`
+    Line,
+    /// A rectangle of columns, started with Ctrl-v
+    Block,
+}
+
+pub struct ViMode {
+    /// The column of the cursor
+    pub x: usize,
+    /// The line of the cursor, counting from the oldest line of the scrollback
+    pub line: usize,
+    /// The kind of selection and where it was started, which runs up to the
+    /// cursor
+    pub selection: Option<(ViSelection, usize, usize)>,
+    /// Set after `g`, waiting for the second `g` of `gg`
+    pub pending_g: bool,
+}
+
+impl ViMode {
+    pub fn new(x: usize, line: usize) -> ViMode {
+        ViMode {
+            x,
+            line,
+            selection: None,
+            pending_g: false,
+        }
+    }
+
+    /// Start a selection of the given kind at the cursor, or stop it when it is
+    /// already of that kind
+    pub fn toggle_selection(&mut self, kind: ViSelection) {
+        self.selection = match self.selection {
+            Some((current, _, _)) if current == kind => None,
+            Some((_, x, line)) => Some((kind, x, line)),
+            None => Some((kind, self.x, self.line)),
+        };
+    }
+
+    /// The columns of the line that are selected, as a half open range up to
+    /// at most `width`
+    pub fn selected(&self, line: usize, width: usize) -> Option<(usize, usize)> {
+        let (kind, start_x, start_line) = self.selection?;
+        let (first, last) = (
+            cmp::min((start_line, start_x), (self.line, self.x)),
+            cmp::max((start_line, start_x), (self.line, self.x)),
+        );
+        if line < first.0 || line > last.0 {
+            return None;
+        }
+
+        let (start, end) = match kind {
+            ViSelection::Char => (
+                if line == first.0 { first.1 } else { 0 },
+                if line == last.0 { last.1 + 1 } else { width },
+            ),
+            ViSelection::Line => (0, width),
+            ViSelection::Block => (cmp::min(start_x, self.x), cmp::max(start_x, self.x) + 1),
+        };
+        Some((cmp::min(start, width), cmp::min(end, width)))
+    }
+
+    pub fn selects(&self, line: usize, x: usize, width: usize) -> bool {
+        self.selected(line, width)
+            .is_some_and(|(start, end)| start <= x && x < end)
+    }
+}